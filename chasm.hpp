@@ -14,7 +14,7 @@ struct TranslationOutput;
 extern "C" {
 
 /// takes an assembly-c_str and parses it.
-/// if parsing fails, `parse_asm` will set `*err` and return `nullptr`
+/// if parsing fails, `parse_asm` will set `*err` according to the first error and return `nullptr`
 /// else `parse_asm` will return a pointer to an intermediate representation of the assembled instructions
 ///
 /// if any of the pointers supplied are invalid or nullptr, the function may crash
@@ -26,9 +26,40 @@ extern "C" {
 /// - `-3`: Unexpected Token
 /// - `-4`: Label Not Resolved Error
 /// - `-5`: Cannot set location
+/// - `-6`: Immediate Out Of Range
+/// - `-7`: Offset Out Of Range
+/// - `-8`: Lex Error
+/// - `-9`: Duplicate Label
+/// - `-10`: Unknown Mnemonic
+/// - `-12`: Recursive Constant
+/// - `-13`: Constant Used Before Definition
+/// - `-14`: Invalid Expression
+/// - `-15`: Unterminated Macro Or Struct
+/// - `-16`: Wrong Number Of Macro Arguments
+/// - `-17`: Recursive Macro
+/// - `-18`: Unmatched Conditional
+/// - `-19`: Unterminated Conditional
+/// - `-20`: Invalid Define
+/// - `-21`: Include Not Found
+/// - `-22`: Recursive Include
+/// - `-23`: Instruction At Odd Address
+/// - `-24`: Unaligned Label
+/// - `-25`: Data In BSS
+/// - `-26`: Invalid Section Address
+/// - `-27`: Trap Redefinition
+/// - `-28`: Misaligned Field
+/// - `-29`: Alignment Outside Text
 const TranslationOutput *parse_asm(const char *assembly,
                                    int32_t *err);
 
+/// like `parse_asm`, but defines the `count` constants in `defines` before parsing,
+/// each given as `NAME` or `NAME=value` like `-D` on the command line.
+/// if one of them is malformed, `*err` is set to `-20`
+const TranslationOutput *parse_asm_with_defines(const char *assembly,
+                                                const char *const *defines,
+                                                uintptr_t count,
+                                                int32_t *err);
+
 const TranslationOutput *parse_asm_extend(const char *assembly,
                                           const TranslationOutput *previous,
                                           int32_t *err);
@@ -46,6 +77,29 @@ const TranslationOutput *parse_asm_extend(const char *assembly,
 /// - `-3`: Unexpected Token
 /// - `-4`: Label Not Resolved Error
 /// - `-5`: Cannot set location
+/// - `-6`: Immediate Out Of Range
+/// - `-7`: Offset Out Of Range
+/// - `-8`: Lex Error
+/// - `-9`: Duplicate Label
+/// - `-10`: Unknown Mnemonic
+/// - `-12`: Recursive Constant
+/// - `-13`: Constant Used Before Definition
+/// - `-14`: Invalid Expression
+/// - `-15`: Unterminated Macro Or Struct
+/// - `-16`: Wrong Number Of Macro Arguments
+/// - `-17`: Recursive Macro
+/// - `-18`: Unmatched Conditional
+/// - `-19`: Unterminated Conditional
+/// - `-20`: Invalid Define
+/// - `-21`: Include Not Found
+/// - `-22`: Recursive Include
+/// - `-23`: Instruction At Odd Address
+/// - `-24`: Unaligned Label
+/// - `-25`: Data In BSS
+/// - `-26`: Invalid Section Address
+/// - `-27`: Trap Redefinition
+/// - `-28`: Misaligned Field
+/// - `-29`: Alignment Outside Text
 const uint16_t *link_asm(const TranslationOutput *translation, int32_t *err, uintptr_t *len);
 
 } // extern "C"
@@ -20,12 +20,60 @@
 //!
 //! ```
 
-use crate::parser::translator::{link, translate, LinkError, ParseError, TranslationOutput};
+use crate::parser::translator::{
+    link, parse_define, translate, translate_with, LinkError, Options, ParseError,
+    TranslationOutput,
+};
 use libc::c_char;
 use std::ffi::CStr;
 
+/// error code reported for the first error of a failed `parse_asm`
+fn parse_error_code(error: &ParseError) -> i32 {
+    match error {
+        ParseError::StatementSyntaxError(_, _) => -1,
+        ParseError::LabelSyntaxError(_, _) => -2,
+        ParseError::UnexpectedToken(_, _) => -3,
+        ParseError::InvalidLocation(_) => -5,
+        ParseError::ImmediateOutOfRange { .. } => -6,
+        ParseError::LexError(_, _) => -8,
+        ParseError::DuplicateLabel { .. }
+        | ParseError::DuplicateConstant { .. }
+        | ParseError::DuplicateMacro { .. } => -9,
+        ParseError::UnknownMnemonic { .. } => -10,
+        ParseError::RecursiveConstant(_, _) => -12,
+        ParseError::ConstantUsedBeforeDefinition(_, _) => -13,
+        ParseError::InvalidExpression(_) => -14,
+        ParseError::UnterminatedMacro(_, _) | ParseError::UnterminatedStruct(_, _) => -15,
+        ParseError::MacroArguments { .. } => -16,
+        ParseError::RecursiveMacro(_, _) => -17,
+        ParseError::UnmatchedConditional(_, _) => -18,
+        ParseError::UnterminatedConditional(_) => -19,
+        ParseError::IncludeNotFound(_, _) => -21,
+        ParseError::RecursiveInclude(_, _) => -22,
+        ParseError::OddAddress(_) => -23,
+        ParseError::DataInBss(_) => -25,
+        ParseError::AlignmentOutsideText(_, _) => -29,
+        ParseError::TrapRedefinition { .. } => -27,
+        ParseError::MisalignedField(_, _) => -28,
+        ParseError::InMacro { error, .. } => parse_error_code(error),
+    }
+}
+
+/// error code reported for the first error of a failed `link_asm`
+fn link_error_code(error: &LinkError) -> i32 {
+    match error {
+        LinkError::LabelNotResolvedError { .. } => -4,
+        LinkError::OffsetOutOfRange { .. } => -7,
+        LinkError::DuplicateLabel { .. } => -9,
+        LinkError::ImmediateOutOfRange { .. } => -6,
+        LinkError::InvalidExpression(_) => -14,
+        LinkError::UnalignedLabel { .. } => -24,
+        LinkError::InvalidSectionAddress { .. } => -26,
+    }
+}
+
 /// takes an assembly-c_str and parses it.
-/// if parsing fails, `parse_asm` will set `*err` and return `nullptr`
+/// if parsing fails, `parse_asm` will set `*err` according to the first error and return `nullptr`
 /// else `parse_asm` will return a pointer to an intermediate representation of the assembled instructions
 ///
 /// if any of the pointers supplied are invalid or nullptr, the function may crash
@@ -37,6 +85,29 @@ use std::ffi::CStr;
 /// - `-3`: Unexpected Token
 /// - `-4`: Label Not Resolved Error
 /// - `-5`: Cannot set location
+/// - `-6`: Immediate Out Of Range
+/// - `-7`: Offset Out Of Range
+/// - `-8`: Lex Error
+/// - `-9`: Duplicate Label
+/// - `-10`: Unknown Mnemonic
+/// - `-12`: Recursive Constant
+/// - `-13`: Constant Used Before Definition
+/// - `-14`: Invalid Expression
+/// - `-15`: Unterminated Macro Or Struct
+/// - `-16`: Wrong Number Of Macro Arguments
+/// - `-17`: Recursive Macro
+/// - `-18`: Unmatched Conditional
+/// - `-19`: Unterminated Conditional
+/// - `-20`: Invalid Define
+/// - `-21`: Include Not Found
+/// - `-22`: Recursive Include
+/// - `-23`: Instruction At Odd Address
+/// - `-24`: Unaligned Label
+/// - `-25`: Data In BSS
+/// - `-26`: Invalid Section Address
+/// - `-27`: Trap Redefinition
+/// - `-28`: Misaligned Field
+/// - `-29`: Alignment Outside Text
 #[allow(clippy::not_unsafe_ptr_arg_deref)]
 #[no_mangle]
 pub extern "C" fn parse_asm(assembly: *const c_char, err: *mut i32) -> *const TranslationOutput {
@@ -48,13 +119,52 @@ pub extern "C" fn parse_asm(assembly: *const c_char, err: *mut i32) -> *const Tr
     let assembly = assembly.to_str().unwrap();
     match translate(assembly) {
         Ok(translation) => Box::into_raw(Box::new(translation)) as *const TranslationOutput,
-        Err(error) => {
-            match error {
-                ParseError::StatementSyntaxError(_, _) => unsafe { *err = -1 },
-                ParseError::LabelSyntaxError(_, _) => unsafe { *err = -2 },
-                ParseError::UnexpectedToken(_, _) => unsafe { *err = -3 },
-                ParseError::InvalidLocation(_) => unsafe { *err = -5 },
+        Err(errors) => {
+            unsafe { *err = parse_error_code(&errors[0]) };
+
+            std::ptr::null::<TranslationOutput>()
+        }
+    }
+}
+
+/// like `parse_asm`, but defines the `count` constants in `defines` before parsing,
+/// each given as `NAME` or `NAME=value` like `-D` on the command line.
+/// if one of them is malformed, `*err` is set to `-20`
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn parse_asm_with_defines(
+    assembly: *const c_char,
+    defines: *const *const c_char,
+    count: usize,
+    err: *mut i32,
+) -> *const TranslationOutput {
+    let assembly = unsafe {
+        assert!(!assembly.is_null());
+        CStr::from_ptr(assembly)
+    };
+    let assembly = assembly.to_str().unwrap();
+
+    let mut options = Options::default();
+    for i in 0..count {
+        let define = unsafe {
+            assert!(!defines.is_null());
+            let define = *defines.add(i);
+            assert!(!define.is_null());
+            CStr::from_ptr(define)
+        };
+        match define.to_str().ok().and_then(parse_define) {
+            Some(define) => options.defines.push(define),
+            None => {
+                unsafe { *err = -20 };
+                return std::ptr::null::<TranslationOutput>();
             }
+        }
+    }
+
+    match translate_with(assembly, 0, &options) {
+        Ok(translation) => Box::into_raw(Box::new(translation)) as *const TranslationOutput,
+        Err(errors) => {
+            unsafe { *err = parse_error_code(&errors[0]) };
 
             std::ptr::null::<TranslationOutput>()
         }
@@ -85,13 +195,8 @@ pub extern "C" fn parse_asm_extend(
             println!("{previous:?}");
             Box::into_raw(Box::new(previous)) as *const TranslationOutput
         }
-        Err(error) => {
-            match error {
-                ParseError::StatementSyntaxError(_, _) => unsafe { *err = -1 },
-                ParseError::LabelSyntaxError(_, _) => unsafe { *err = -2 },
-                ParseError::UnexpectedToken(_, _) => unsafe { *err = -3 },
-                ParseError::InvalidLocation(_) => unsafe { *err = -5 },
-            }
+        Err(errors) => {
+            unsafe { *err = parse_error_code(&errors[0]) };
 
             std::ptr::null::<TranslationOutput>()
         }
@@ -111,6 +216,29 @@ pub extern "C" fn parse_asm_extend(
 /// - `-3`: Unexpected Token
 /// - `-4`: Label Not Resolved Error
 /// - `-5`: Cannot set location
+/// - `-6`: Immediate Out Of Range
+/// - `-7`: Offset Out Of Range
+/// - `-8`: Lex Error
+/// - `-9`: Duplicate Label
+/// - `-10`: Unknown Mnemonic
+/// - `-12`: Recursive Constant
+/// - `-13`: Constant Used Before Definition
+/// - `-14`: Invalid Expression
+/// - `-15`: Unterminated Macro Or Struct
+/// - `-16`: Wrong Number Of Macro Arguments
+/// - `-17`: Recursive Macro
+/// - `-18`: Unmatched Conditional
+/// - `-19`: Unterminated Conditional
+/// - `-20`: Invalid Define
+/// - `-21`: Include Not Found
+/// - `-22`: Recursive Include
+/// - `-23`: Instruction At Odd Address
+/// - `-24`: Unaligned Label
+/// - `-25`: Data In BSS
+/// - `-26`: Invalid Section Address
+/// - `-27`: Trap Redefinition
+/// - `-28`: Misaligned Field
+/// - `-29`: Alignment Outside Text
 #[no_mangle]
 #[allow(clippy::not_unsafe_ptr_arg_deref)]
 pub extern "C" fn link_asm(
@@ -134,10 +262,8 @@ pub extern "C" fn link_asm(
 
             ptr
         }
-        Err(error) => {
-            match error {
-                LinkError::LabelNotResolvedError(_, _) => unsafe { *err = -4 },
-            }
+        Err(errors) => {
+            unsafe { *err = link_error_code(&errors[0]) };
             std::ptr::null::<u16>()
         }
     }
@@ -1,3 +1,4 @@
 pub mod opcodes;
 pub mod ops;
+pub mod pseudo;
 pub mod registers;
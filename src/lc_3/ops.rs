@@ -4,7 +4,7 @@ use super::opcodes::Ops;
 
 fn sign_extend(num: i32, len: u32) -> u32 {
     let tmp = (-num) as u32;
-    let mask = (1 << (len + 1)) - 1;
+    let mask = (1 << len) - 1;
     ((!tmp & mask) + 1) & mask
 }
 /// add constant value
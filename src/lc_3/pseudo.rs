@@ -0,0 +1,152 @@
+//! pseudo-instructions, which stand for one or more real instructions
+
+use crate::lc_3::registers::Registers;
+
+use super::ops::{add_const, add_reg, and_const, branch, load_word, lshf, not, store_word};
+
+/// the register `PUSH` and `POP` use as stack pointer
+pub const STACK_POINTER: Registers = Registers::R6;
+
+/// do nothing, a branch that is never taken
+pub fn nop() -> u16 {
+    branch(false, false, false, 0)
+}
+
+/// copy a register
+pub fn mov(dest: Registers, source: Registers) -> u16 {
+    add_const(dest, source, 0)
+}
+
+/// set a register to zero
+pub fn clr(dest: Registers) -> u16 {
+    and_const(dest, dest, 0)
+}
+
+pub fn inc(dest: Registers) -> u16 {
+    add_const(dest, dest, 1)
+}
+
+pub fn dec(dest: Registers) -> u16 {
+    add_const(dest, dest, -1i8 as u8)
+}
+
+/// two's complement
+pub fn neg(dest: Registers, source: Registers) -> Vec<u16> {
+    vec![not(dest, source), inc(dest)]
+}
+
+/// `dest = source1 - source2`, without changing any other register
+pub fn sub(dest: Registers, source1: Registers, source2: Registers) -> Vec<u16> {
+    if dest != source2 {
+        // !(!a + b) = a - b
+        vec![
+            not(dest, source1),
+            add_reg(dest, dest, source2),
+            not(dest, dest),
+        ]
+    } else if dest != source1 {
+        vec![not(dest, source2), add_reg(dest, dest, source1), inc(dest)]
+    } else {
+        vec![clr(dest)]
+    }
+}
+
+/// store a register on top of the stack, which grows downwards
+pub fn push(source: Registers) -> Vec<u16> {
+    vec![
+        add_const(STACK_POINTER, STACK_POINTER, -2i8 as u8),
+        store_word(source, STACK_POINTER, 0),
+    ]
+}
+
+/// load a register from the top of the stack and remove it
+pub fn pop(dest: Registers) -> Vec<u16> {
+    vec![
+        load_word(dest, STACK_POINTER, 0),
+        add_const(STACK_POINTER, STACK_POINTER, 2),
+    ]
+}
+
+/// load any 16 bit value, four bits at a time.
+/// values fitting into imm5 take two words, others up to eight
+pub fn ldi16(dest: Registers, value: u16) -> Vec<u16> {
+    let mut words = vec![clr(dest)];
+    if (-16..16).contains(&(value as i16)) {
+        if value != 0 {
+            words.push(add_const(dest, dest, value as u8));
+        }
+        return words;
+    }
+    let mut shift = 0;
+    for nibble in (0..4).rev().map(|index| (value >> (4 * index)) & 0xf) {
+        if words.len() > 1 {
+            shift += 4;
+        }
+        if nibble != 0 {
+            if shift > 0 {
+                words.push(lshf(dest, dest, shift));
+                shift = 0;
+            }
+            words.push(add_const(dest, dest, nibble as u8));
+        }
+    }
+    if shift > 0 {
+        words.push(lshf(dest, dest, shift));
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ldi16, sub};
+    use crate::lc_3::registers::Registers::{R0, R1, R2};
+
+    /// runs ADD, AND, XOR, SHF on eight registers
+    fn run(words: &[u16], registers: &mut [u16; 8]) {
+        for &word in words {
+            let dest = (word >> 9 & 0b111) as usize;
+            let source = registers[(word >> 6 & 0b111) as usize];
+            let operand = if word & 0b10_0000 != 0 {
+                ((word << 11) as i16 >> 11) as u16
+            } else {
+                registers[(word & 0b111) as usize]
+            };
+            registers[dest] = match word >> 12 {
+                0b0001 => source.wrapping_add(operand),
+                0b0101 => source & operand,
+                0b1001 => source ^ operand,
+                0b1101 => {
+                    assert_eq!(word & 0b11_0000, 0, "only LSHF is used");
+                    source << (word & 0xf)
+                }
+                opcode => panic!("unexpected opcode {opcode:04b}"),
+            };
+        }
+    }
+
+    #[test]
+    fn test_ldi16() {
+        for value in [
+            0, 1, 15, 0xfff0, 0xffff, 16, 0x1000, 0x1234, 0x8000, 0xabcd, 0x0f0f,
+        ] {
+            let words = ldi16(R1, value);
+            let mut registers = [0x5555; 8];
+            run(&words, &mut registers);
+            assert_eq!(registers[1], value, "for {value:#x}");
+            assert!(words.len() <= 8);
+        }
+        assert_eq!(ldi16(R1, 0x1000).len(), 3);
+        assert_eq!(ldi16(R1, 0xffff).len(), 2);
+    }
+
+    #[test]
+    fn test_sub() {
+        for (dest, source1, source2) in [(R0, R1, R2), (R0, R0, R1), (R0, R1, R0), (R0, R0, R0)] {
+            let mut registers: [u16; 8] = [7, 3, 100, 0, 0, 0, 0, 0];
+            let expected = registers[source1 as usize].wrapping_sub(registers[source2 as usize]);
+            run(&sub(dest, source1, source2), &mut registers);
+            assert_eq!(registers[0], expected);
+            assert_eq!(registers[1..3], [3, 100]);
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use std::str::FromStr;
 
 #[allow(dead_code)]
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
 pub enum Registers {
     R0 = 0,
@@ -1,20 +1,28 @@
-use chasm::parser::translator::{link, translate, LinkError, ParseError};
+use chasm::parser::{
+    diagnostic::{Diagnostic, Severity},
+    include::{FileLoader, Loader},
+    source::Sources,
+    translator::{
+        link_with, parse_define, parse_section, parse_trap_name, translate_with_loader, Image,
+        Options, TranslationOutput,
+    },
+    warning::WarningConfig,
+};
 use clap::{Arg, Command, ValueHint};
 use std::{
     collections::HashSet,
-    fs::{self, File},
+    fs::File,
     io::Write,
-    path::Path,
+    iter,
+    path::{Path, PathBuf},
 };
 
-const HEADER_SIZE: usize = 0x0400usize;
-fn write_instructions(path: &Path, instructions: Vec<u16>, debug_print: bool) {
-    let mut buf: Vec<u8> = Vec::with_capacity(instructions.len() << 1);
+fn write_instructions(path: &Path, image: Image, debug_print: bool) {
+    let mut buf: Vec<u8> = Vec::with_capacity(image.words.len() << 1);
 
-    // header
-    for (i, instr) in instructions.iter().enumerate() {
+    for (i, instr) in image.words.iter().enumerate() {
         if debug_print {
-            println!("[{:4x}]  x{instr:04x}", i * 2 + HEADER_SIZE);
+            println!("[{:4x}]  x{instr:04x}", image.origin as usize + i * 2);
         }
 
         buf.push((instr & 0xff) as u8);
@@ -30,11 +38,42 @@ macro_rules! exit_failure {
         std::process::exit(-1)
     };
     ($msg:expr) => {
-        eprintln!("{}", $msg);
+        eprint!("{}", $msg);
         std::process::exit(-1)
     };
 }
 
+/// how diagnostics are written to stderr
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ErrorFormat {
+    Human,
+    /// one JSON object per line
+    Json,
+}
+
+impl ErrorFormat {
+    fn emit(self, diagnostic: &Diagnostic, sources: &Sources) {
+        match self {
+            ErrorFormat::Human => eprintln!("{}", diagnostic.render(sources)),
+            ErrorFormat::Json => eprintln!("{}", diagnostic.to_json(sources)),
+        }
+    }
+}
+
+fn report_errors(errors: &[Diagnostic], sources: &Sources, format: ErrorFormat) -> ! {
+    for error in errors {
+        format.emit(error, sources);
+    }
+    if format == ErrorFormat::Json {
+        exit_failure!();
+    }
+    let plural = if errors.len() == 1 { "" } else { "s" };
+    exit_failure!(format!(
+        "error: aborting due to {} previous error{plural}\n",
+        errors.len()
+    ));
+}
+
 fn verify_includes<'a>(includes: Vec<&'a str>, main_name: &'a str) -> Vec<&'a str> {
     let mut seen = HashSet::new();
     let mut incl = vec![];
@@ -77,6 +116,51 @@ fn main() {
                 .required(false)
                 .long("print-debug"),
         )
+        .arg(
+            Arg::new("error-limit")
+                .takes_value(true)
+                .required(false)
+                .long("error-limit")
+                .value_name("N")
+                .validator(|limit| limit.parse::<usize>())
+                .help("Stop after <N> errors"),
+        )
+        .arg(
+            Arg::new("classic")
+                .takes_value(false)
+                .required(false)
+                .long("classic")
+                .help("Accept the syntax of classic LC-3 tools, like lower case mnemonics and 0x1F"),
+        )
+        .arg(
+            Arg::new("error-format")
+                .takes_value(true)
+                .required(false)
+                .long("error-format")
+                .value_name("FORMAT")
+                .possible_values(["human", "json"])
+                .default_value("human")
+                .help("Print diagnostics for humans or as one JSON object per line"),
+        )
+        .arg(
+            Arg::new("warnings")
+                .takes_value(true)
+                .required(false)
+                .multiple_occurrences(true)
+                .short('W')
+                .value_name("WARNING")
+                .help("Enable <warning>, disable it with no-<warning>, or turn warnings into errors with error[=<warning>]"),
+        )
+        .arg(
+            Arg::new("defines")
+                .takes_value(true)
+                .required(false)
+                .multiple_occurrences(true)
+                .short('D')
+                .value_name("NAME[=VALUE]")
+                .validator(|define| parse_define(define).ok_or("expected NAME or NAME=VALUE"))
+                .help("Define the constant <NAME> as <VALUE>, or 1, overriding its definition in the source"),
+        )
         .arg(
             Arg::new("includes")
                 .takes_value(true)
@@ -86,6 +170,36 @@ fn main() {
                 .short('I')
                 .long("include")
                 .value_name("FILENAMES"),
+        )
+        .arg(
+            Arg::new("include-dirs")
+                .takes_value(true)
+                .required(false)
+                .multiple_occurrences(true)
+                .value_hint(ValueHint::DirPath)
+                .long("include-dir")
+                .value_name("DIR")
+                .help("Search <DIR> for files named by .INCLUDE that are not next to the including file"),
+        )
+        .arg(
+            Arg::new("sections")
+                .takes_value(true)
+                .required(false)
+                .multiple_occurrences(true)
+                .long("section")
+                .value_name("NAME=ADDR")
+                .validator(|section| parse_section(section).ok_or("expected NAME=ADDR with an even address"))
+                .help("Place the section <NAME> (text, data or bss) at the byte address <ADDR> instead of after the one before it"),
+        )
+        .arg(
+            Arg::new("traps")
+                .takes_value(true)
+                .required(false)
+                .multiple_occurrences(true)
+                .long("trap")
+                .value_name("NAME=VECTOR")
+                .validator(|trap| parse_trap_name(trap).ok_or("expected NAME=VECTOR with a vector from 0 to 255"))
+                .help("Make <NAME> a mnemonic for TRAP <VECTOR>, like .TRAPDEF"),
         );
     let matches = app.get_matches();
     let outfile = if let Some(outfile) = matches.value_of("outfile") {
@@ -103,85 +217,107 @@ fn main() {
         vec![]
     };
 
-    // read main assembly file
-    let text = fs::read_to_string(infile).expect("read failed");
-
-    // parse main assembly file
-    let mut translation = match translate(&text) {
-        Ok(translation) => translation,
-        Err(err) => {
-            match err {
-                ParseError::StatementSyntaxError(instruction, line_number) => {
-                    eprintln!("{infile}:{line_number} syntax error while parsing {instruction:?}");
-                }
-                ParseError::LabelSyntaxError(label, line_number) => {
-                    eprintln!("{infile}:{line_number} syntax error while parsing label {label:?}");
-                }
-                ParseError::UnexpectedToken(token, line_number) => {
-                    eprintln!("{infile}:{line_number} unexpected token {token}");
-                }
-                ParseError::InvalidLocation(line_number) => {
-                    eprintln!("{infile}:{line_number} cannot set location")
-                }
-            }
-            exit_failure!();
-        }
+    let options = Options {
+        error_limit: matches.value_of_t("error-limit").ok(),
+        classic: matches.is_present("classic"),
+        defines: matches
+            .values_of("defines")
+            .into_iter()
+            .flatten()
+            .filter_map(parse_define)
+            .collect(),
+        sections: matches
+            .values_of("sections")
+            .into_iter()
+            .flatten()
+            .filter_map(parse_section)
+            .collect(),
+        traps: matches
+            .values_of("traps")
+            .into_iter()
+            .flatten()
+            .filter_map(parse_trap_name)
+            .collect(),
+    };
+
+    let format = match matches.value_of("error-format") {
+        Some("json") => ErrorFormat::Json,
+        _ => ErrorFormat::Human,
     };
 
-    // parse included files
-    for included in includes {
-        let source_text = match fs::read_to_string(included) {
-            Ok(source_text) => source_text,
+    let mut warning_config = WarningConfig::default();
+    for flag in matches.values_of("warnings").into_iter().flatten() {
+        if let Err(message) = warning_config.apply(flag) {
+            exit_failure!(format!("error: {message}\n"));
+        }
+    }
+
+    let include_dirs = matches.values_of("include-dirs").into_iter().flatten();
+    let mut loader = FileLoader::new(include_dirs.map(PathBuf::from).collect());
+    let mut translation: Option<TranslationOutput> = None;
+    let mut errors = vec![];
+
+    // parse main assembly file, then the included files
+    for path in iter::once(infile).chain(includes) {
+        let file = match loader.add(Path::new(path)) {
+            Ok(file) => file,
             Err(_) => {
-                eprintln!("couldnt read {included:?}");
+                eprintln!("couldnt read {path:?}");
                 exit_failure!();
             }
         };
+        // a file named by an `.INCLUDE` before is part of the translation already
+        if !loader.translate_once(file) {
+            continue;
+        }
+        let text = loader.sources().get(file).unwrap().text.clone();
 
-        let lib_translation = match translate(&source_text) {
-            Ok(translation) => translation,
-            Err(err) => {
-                match err {
-                    ParseError::StatementSyntaxError(instruction, line_number) => {
-                        eprintln!(
-                            "{infile}:{line_number} syntax error while parsing {instruction:?} in ${included:?}"
-                        );
-                    }
-                    ParseError::LabelSyntaxError(label, line_number) => {
-                        eprintln!(
-                            "{infile}:{line_number} syntax error while parsing label {label:?} in ${included:?}"
-                        );
-                    }
-                    ParseError::UnexpectedToken(token, line_number) => {
-                        eprintln!(
-                            "{infile}:{line_number} unexpected token {token} in ${included:?}"
-                        );
-                    }
-                    ParseError::InvalidLocation(line_number) => {
-                        eprintln!("{infile}:{line_number} cannot set location")
-                    }
-                }
-                exit_failure!();
+        match translate_with_loader(&text, file, &options, &mut loader) {
+            Ok(unit) => match translation.as_mut() {
+                Some(translation) => translation.extend(unit),
+                None => translation = Some(unit),
+            },
+            Err(unit_errors) => errors.extend(unit_errors.iter().map(Diagnostic::from)),
+        }
+
+        if let Some(limit) = options.error_limit {
+            if errors.len() >= limit {
+                errors.truncate(limit);
+                break;
             }
-        };
+        }
+    }
+
+    let sources = loader.sources();
+    if !errors.is_empty() {
+        report_errors(&errors, sources, format);
+    }
 
-        translation.extend(lib_translation);
+    let translation = translation.unwrap();
+    let warnings: Vec<_> = translation
+        .warnings()
+        .iter()
+        .filter_map(|warning| warning_config.diagnostic(warning))
+        .collect();
+    for warning in warnings.iter().filter(|w| w.severity == Severity::Warning) {
+        format.emit(warning, sources);
+    }
+    let errors: Vec<_> = warnings
+        .into_iter()
+        .filter(|w| w.severity == Severity::Error)
+        .collect();
+    if !errors.is_empty() {
+        report_errors(&errors, sources, format);
     }
 
     // link files (aka resolve addresses of labels)
-    match link(translation) {
-        Ok(instructions) => {
-            write_instructions(outfile, instructions, matches.is_present("print-debug"));
+    match link_with(translation, &options) {
+        Ok(image) => {
+            write_instructions(outfile, image, matches.is_present("print-debug"));
         }
-        Err(err) => {
-            match err {
-                LinkError::LabelNotResolvedError(label, line_number) => {
-                    eprintln!(
-                        "{infile}:{line_number} could not resolve label {label:?} during link"
-                    )
-                }
-            }
-            exit_failure!();
+        Err(errors) => {
+            let errors: Vec<_> = errors.iter().map(Diagnostic::from).collect();
+            report_errors(&errors, sources, format);
         }
     }
 }
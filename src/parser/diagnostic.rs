@@ -0,0 +1,362 @@
+use std::fmt::{self, Write};
+
+use super::{
+    source::{Sources, Span},
+    translator::{LinkError, ParseError},
+    warning::{Warning, WarningKind},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// stable identifier of what a [`Diagnostic`] is about, for tools to match on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Code {
+    /// an error code like `E0001`
+    Error(&'static str),
+    Warning(WarningKind),
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Code::Error(code) => write!(f, "{code}"),
+            Code::Warning(kind) => write!(f, "{}", kind.name()),
+        }
+    }
+}
+
+/// a message about a location in the source, ready to be shown to the user
+#[derive(Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: Code,
+    pub message: String,
+    pub span: Span,
+    /// additional remarks shown below the quoted source
+    pub notes: Vec<Note>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Note {
+    pub message: String,
+    /// another location the note refers to
+    pub span: Option<Span>,
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: String, span: Span) -> Self {
+        Self {
+            severity: Severity::Error,
+            code: Code::Error(code),
+            message,
+            span,
+            notes: vec![],
+        }
+    }
+
+    pub fn warning(kind: WarningKind, message: String, span: Span) -> Self {
+        Self {
+            severity: Severity::Warning,
+            code: Code::Warning(kind),
+            message,
+            span,
+            notes: vec![],
+        }
+    }
+
+    pub fn with_note(mut self, message: String) -> Self {
+        self.notes.push(Note {
+            message,
+            span: None,
+        });
+        self
+    }
+
+    /// adds a note quoting the source at `span`
+    pub fn with_span_note(mut self, message: String, span: Span) -> Self {
+        self.notes.push(Note {
+            message,
+            span: Some(span),
+        });
+        self
+    }
+
+    /// renders the diagnostic like rustc does,
+    /// quoting the offending line and underlining the token with carets
+    pub fn render(&self, sources: &Sources) -> String {
+        let mut out = match self.code {
+            Code::Error(code) => format!("{}[{code}]: {}\n", self.severity, self.message),
+            Code::Warning(kind) => {
+                format!("{}: {} [-W{}]\n", self.severity, self.message, kind.name())
+            }
+        };
+        let gutter = snippet(&mut out, sources, self.span);
+        for note in self.notes.iter().filter(|note| note.span.is_none()) {
+            let _ = writeln!(out, "{gutter} = {}", note.message);
+        }
+        for note in &self.notes {
+            if let Some(span) = note.span {
+                let _ = writeln!(out, "note: {}", note.message);
+                snippet(&mut out, sources, span);
+            }
+        }
+
+        out
+    }
+
+    /// renders the diagnostic as a single line JSON object
+    /// with the fields `severity`, `code`, `file`, `line`, `column`, `message` and `notes`
+    pub fn to_json(&self, sources: &Sources) -> String {
+        let mut out = format!(
+            "{{\"severity\":\"{}\",\"code\":\"{}\",{},\"message\":{},\"notes\":[",
+            self.severity,
+            self.code,
+            json_location(sources, self.span),
+            json_string(&self.message)
+        );
+        for (i, note) in self.notes.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            let _ = write!(out, "{{\"message\":{}", json_string(&note.message));
+            if let Some(span) = note.span {
+                let _ = write!(out, ",{}", json_location(sources, span));
+            }
+            out.push('}');
+        }
+        out.push_str("]}");
+
+        out
+    }
+}
+
+/// the `file`, `line` and `column` fields of a JSON diagnostic
+fn json_location(sources: &Sources, span: Span) -> String {
+    let name = sources
+        .get(span.file)
+        .map_or("<unknown>", |file| file.name.as_str());
+    format!(
+        "\"file\":{},\"line\":{},\"column\":{}",
+        json_string(name),
+        span.line,
+        span.column
+    )
+}
+
+/// quotes and escapes `text` as a JSON string
+fn json_string(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for ch in text.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            ch if (ch as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", ch as u32);
+            }
+            ch => out.push(ch),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// writes the location of `span` and the line it is in,
+/// returning the gutter to align further lines with
+fn snippet(out: &mut String, sources: &Sources, span: Span) -> String {
+    let name = sources
+        .get(span.file)
+        .map_or("<unknown>", |file| file.name.as_str());
+    let line_number = span.line.to_string();
+    let gutter = " ".repeat(line_number.len());
+    let _ = writeln!(out, "{gutter}--> {name}:{}:{}", span.line, span.column);
+
+    if let Some(line) = sources.line(span) {
+        let column = (span.column as usize).saturating_sub(1).min(line.len());
+        // keep tabs so the carets line up with the quoted line
+        let indent: String = line[..column]
+            .chars()
+            .map(|ch| if ch == '\t' { '\t' } else { ' ' })
+            .collect();
+        let width = (span.end - span.start).min(line.len() - column).max(1);
+        let _ = writeln!(out, "{gutter} |");
+        let _ = writeln!(out, "{line_number} | {line}");
+        let _ = writeln!(out, "{gutter} | {indent}{}", "^".repeat(width));
+    }
+
+    gutter
+}
+
+impl From<&ParseError> for Diagnostic {
+    fn from(error: &ParseError) -> Self {
+        if let ParseError::InMacro { error, name, call } = error {
+            return Self::from(error.as_ref())
+                .with_span_note(format!("in this expansion of macro {name:?}"), *call);
+        }
+        let mut diagnostic = Self::error(error.code(), error.to_string(), error.span());
+        if let Some(help) = error.help() {
+            diagnostic = diagnostic.with_note(format!("help: {help}"));
+        }
+        match error {
+            ParseError::DuplicateLabel {
+                label: name, first, ..
+            }
+            | ParseError::DuplicateConstant { name, first, .. }
+            | ParseError::DuplicateMacro { name, first, .. } => {
+                diagnostic.with_span_note(format!("{name:?} is first defined here"), *first)
+            }
+            _ => diagnostic,
+        }
+    }
+}
+
+impl From<&LinkError> for Diagnostic {
+    fn from(error: &LinkError) -> Self {
+        let mut diagnostic = Self::error(error.code(), error.to_string(), error.span());
+        if let Some(help) = error.help() {
+            diagnostic = diagnostic.with_note(format!("help: {help}"));
+        }
+        match error {
+            LinkError::DuplicateLabel { label, first, .. } => {
+                diagnostic.with_span_note(format!("{label:?} is first defined here"), *first)
+            }
+            _ => diagnostic,
+        }
+    }
+}
+
+impl From<&Warning> for Diagnostic {
+    fn from(warning: &Warning) -> Self {
+        Self::warning(warning.kind(), warning.to_string(), warning.span())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Diagnostic;
+    use crate::parser::{
+        source::Sources,
+        translator::{link, translate_with, Options},
+        warning::Warning,
+    };
+
+    #[test]
+    fn test_render() {
+        let mut sources = Sources::new();
+        sources.add("main.asm", "RET\n".to_owned());
+        let text = "AND R0, R0, #0\n\tADD R0, R9x, R0\n";
+        let file = sources.add("lib.asm", text.to_owned());
+        let errors =
+            translate_with(text, file, &Options::default()).expect_err("R9x is not a register");
+        let expected = [
+            "error[E0001]: syntax error while parsing \"ADD\"",
+            " --> lib.asm:2:10",
+            "  |",
+            "2 | \tADD R0, R9x, R0",
+            "  | \t        ^^^",
+            "",
+        ];
+        assert_eq!(
+            Diagnostic::from(&errors[0]).render(&sources),
+            expected.join("\n")
+        );
+    }
+
+    #[test]
+    fn test_render_span_note() {
+        let mut sources = Sources::new();
+        let first = "loop:\n";
+        let first_file = sources.add("main.asm", first.to_owned());
+        let second = "RET\n  loop:\n";
+        let second_file = sources.add("lib.asm", second.to_owned());
+        let options = Options::default();
+        let mut translation = translate_with(first, first_file, &options).unwrap();
+        translation.extend(translate_with(second, second_file, &options).unwrap());
+        let errors = link(translation).expect_err("loop is defined twice");
+        let expected = [
+            "error[E0009]: label \"loop\" is defined multiple times",
+            " --> lib.asm:2:3",
+            "  |",
+            "2 |   loop:",
+            "  |   ^^^^",
+            "note: \"loop\" is first defined here",
+            " --> main.asm:1:1",
+            "  |",
+            "1 | loop:",
+            "  | ^^^^",
+            "",
+        ];
+        assert_eq!(
+            Diagnostic::from(&errors[0]).render(&sources),
+            expected.join("\n")
+        );
+    }
+
+    #[test]
+    fn test_render_macro_expansion() {
+        let mut sources = Sources::new();
+        let text = ".MACRO CLEAR reg\n  AND reg, reg, #32\n.ENDM\nCLEAR R1\n";
+        let file = sources.add("main.asm", text.to_owned());
+        let errors = translate_with(text, file, &Options::default()).expect_err("32 is too large");
+        let expected = [
+            "error[E0006]: 32 does not fit into imm5, expected a value from -16 to 15",
+            " --> main.asm:2:17",
+            "  |",
+            "2 |   AND reg, reg, #32",
+            "  |                 ^^^",
+            "note: in this expansion of macro \"CLEAR\"",
+            " --> main.asm:4:1",
+            "  |",
+            "4 | CLEAR R1",
+            "  | ^^^^^",
+            "",
+        ];
+        assert_eq!(
+            Diagnostic::from(&errors[0]).render(&sources),
+            expected.join("\n")
+        );
+    }
+
+    #[test]
+    fn test_json() {
+        let mut sources = Sources::new();
+        let first = "loop:\n";
+        let first_file = sources.add("dir\\main.asm", first.to_owned());
+        let second = "RET\n  loop:\n";
+        let second_file = sources.add("lib.asm", second.to_owned());
+        let options = Options::default();
+        let mut translation = translate_with(first, first_file, &options).unwrap();
+        translation.extend(translate_with(second, second_file, &options).unwrap());
+        let errors = link(translation).expect_err("loop is defined twice");
+        let expected = concat!(
+            r#"{"severity":"error","code":"E0009","file":"lib.asm","line":2,"column":3,"#,
+            r#""message":"label \"loop\" is defined multiple times","#,
+            r#""notes":[{"message":"\"loop\" is first defined here","#,
+            r#""file":"dir\\main.asm","line":1,"column":1}]}"#,
+        );
+        assert_eq!(Diagnostic::from(&errors[0]).to_json(&sources), expected);
+
+        let warning = Warning::UnreachableCode(errors[0].span());
+        let expected = concat!(
+            r#"{"severity":"warning","code":"unreachable-code","file":"lib.asm","line":2,"#,
+            r#""column":3,"message":"unreachable instruction","notes":[]}"#,
+        );
+        assert_eq!(Diagnostic::from(&warning).to_json(&sources), expected);
+    }
+}
@@ -0,0 +1,366 @@
+use std::{collections::HashMap, fmt};
+
+use super::source::Span;
+
+/// the value of a constant or an immediate operand, like `(SIZE - 1) << 2` or `end - start`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Number(i32),
+    /// the name of a constant or a label
+    Symbol(String, Span),
+    /// `$`, the byte address of the statement the expression belongs to
+    Location(Span),
+    Unary(UnaryOp, Box<Expr>),
+    /// an operation and the span of its operator
+    Binary(BinaryOp, Box<Expr>, Box<Expr>, Span),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Negate,
+    /// bitwise not, `~`
+    Not,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+    ShiftLeft,
+    ShiftRight,
+    And,
+    Xor,
+    Or,
+}
+
+impl BinaryOp {
+    /// how tightly the operator binds, higher binds tighter, as in C
+    pub fn precedence(self) -> u8 {
+        match self {
+            BinaryOp::Or => 1,
+            BinaryOp::Xor => 2,
+            BinaryOp::And => 3,
+            BinaryOp::ShiftLeft | BinaryOp::ShiftRight => 4,
+            BinaryOp::Add | BinaryOp::Subtract => 5,
+            BinaryOp::Multiply | BinaryOp::Divide | BinaryOp::Remainder => 6,
+        }
+    }
+}
+
+impl BinaryOp {
+    fn symbol(self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Subtract => "-",
+            BinaryOp::Multiply => "*",
+            BinaryOp::Divide => "/",
+            BinaryOp::Remainder => "%",
+            BinaryOp::ShiftLeft => "<<",
+            BinaryOp::ShiftRight => ">>",
+            BinaryOp::And => "&",
+            BinaryOp::Xor => "^",
+            BinaryOp::Or => "|",
+        }
+    }
+}
+
+/// writes `expr` as an operand of an operator binding as tightly as `precedence`
+fn write_operand(f: &mut fmt::Formatter<'_>, expr: &Expr, precedence: u8) -> fmt::Result {
+    match expr {
+        Expr::Binary(op, _, _, _) if op.precedence() < precedence => write!(f, "({expr})"),
+        expr => write!(f, "{expr}"),
+    }
+}
+
+/// writes the expression as it could be written in the source, for error messages
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Number(value) => write!(f, "#{value}"),
+            Expr::Symbol(name, _) => write!(f, "{name}"),
+            Expr::Location(_) => write!(f, "$"),
+            Expr::Unary(op, operand) => {
+                write!(
+                    f,
+                    "{}",
+                    match op {
+                        UnaryOp::Negate => "-",
+                        UnaryOp::Not => "~",
+                    }
+                )?;
+                write_operand(f, operand, u8::MAX)
+            }
+            Expr::Binary(op, left, right, _) => {
+                write_operand(f, left, op.precedence())?;
+                write!(f, " {} ", op.symbol())?;
+                write_operand(f, right, op.precedence() + 1)
+            }
+        }
+    }
+}
+
+/// a constant defined with `.EQU NAME, value` or `NAME = value`
+#[derive(Debug, Clone)]
+pub struct Constant {
+    pub value: Expr,
+    /// where the constant is defined
+    pub span: Span,
+}
+
+/// why an [`Expr`] has no value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvalError {
+    /// a symbol that is neither a constant nor a known label
+    Undefined(String, Span),
+    /// a constant whose value depends on itself
+    Recursive(String, Span),
+    /// `$` where the location is not known yet
+    UnknownLocation(Span),
+    DivisionByZero(Span),
+    /// dividing the smallest value by -1, whose quotient does not fit into 32 bits
+    Overflow(Span),
+    /// shifting by a negative amount or by more than 31 bits
+    InvalidShift(i32, Span),
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalError::Undefined(name, _) => write!(f, "{name:?} is not defined"),
+            EvalError::Recursive(name, _) => {
+                write!(f, "constant {name:?} is defined in terms of itself")
+            }
+            EvalError::UnknownLocation(_) => write!(f, "the location is not known here"),
+            EvalError::DivisionByZero(_) => write!(f, "division by zero"),
+            EvalError::Overflow(_) => write!(f, "the quotient does not fit into 32 bits"),
+            EvalError::InvalidShift(amount, _) => {
+                write!(f, "cannot shift by {amount}, expected 0 to 31")
+            }
+        }
+    }
+}
+
+impl EvalError {
+    /// position of the symbol or operator that caused the error
+    pub fn span(&self) -> Span {
+        match self {
+            EvalError::Undefined(_, span)
+            | EvalError::Recursive(_, span)
+            | EvalError::UnknownLocation(span)
+            | EvalError::DivisionByZero(span)
+            | EvalError::Overflow(span)
+            | EvalError::InvalidShift(_, span) => *span,
+        }
+    }
+}
+
+/// the symbols an [`Expr`] is evaluated with
+pub struct Scope<'s> {
+    pub constants: &'s HashMap<String, Constant>,
+    /// byte address of a label, `None` if it is not known
+    pub label: &'s dyn Fn(&str) -> Option<i32>,
+    /// byte address `$` stands for, `None` if it is not known
+    pub location: Option<i32>,
+}
+
+/// whether `name` can name a constant, which is not the case for words like `1` or `-5`
+pub fn is_symbol(name: &str) -> bool {
+    name.starts_with(|ch: char| ch.is_ascii_alphabetic() || ch == '_')
+}
+
+impl Expr {
+    pub fn evaluate(&self, scope: &Scope) -> Result<i32, EvalError> {
+        self.evaluate_nested(scope, &mut vec![])
+    }
+
+    /// evaluates the expression while the constants in `resolving` are being evaluated
+    fn evaluate_nested<'c>(
+        &self,
+        scope: &Scope<'c>,
+        resolving: &mut Vec<&'c str>,
+    ) -> Result<i32, EvalError> {
+        match self {
+            Expr::Number(value) => Ok(*value),
+            Expr::Symbol(name, span) => {
+                let Some((name, constant)) = scope.constants.get_key_value(name) else {
+                    return (scope.label)(name)
+                        .ok_or_else(|| EvalError::Undefined(name.to_owned(), *span));
+                };
+                if resolving.contains(&name.as_str()) {
+                    return Err(EvalError::Recursive(name.to_owned(), *span));
+                }
+                resolving.push(name);
+                let value = constant.value.evaluate_nested(scope, resolving);
+                resolving.pop();
+                value
+            }
+            Expr::Location(span) => scope.location.ok_or(EvalError::UnknownLocation(*span)),
+            Expr::Unary(op, operand) => {
+                let operand = operand.evaluate_nested(scope, resolving)?;
+                Ok(match op {
+                    UnaryOp::Negate => operand.wrapping_neg(),
+                    UnaryOp::Not => !operand,
+                })
+            }
+            Expr::Binary(op, left, right, span) => {
+                let left = left.evaluate_nested(scope, resolving)?;
+                let right = right.evaluate_nested(scope, resolving)?;
+                let shift = || {
+                    u32::try_from(right)
+                        .ok()
+                        .filter(|&amount| amount < 32)
+                        .ok_or(EvalError::InvalidShift(right, *span))
+                };
+                Ok(match op {
+                    BinaryOp::Add => left.wrapping_add(right),
+                    BinaryOp::Subtract => left.wrapping_sub(right),
+                    BinaryOp::Multiply => left.wrapping_mul(right),
+                    BinaryOp::Divide | BinaryOp::Remainder if right == 0 => {
+                        return Err(EvalError::DivisionByZero(*span))
+                    }
+                    BinaryOp::Divide => {
+                        left.checked_div(right).ok_or(EvalError::Overflow(*span))?
+                    }
+                    // the remainder of `i32::MIN % -1` is 0, which does fit
+                    BinaryOp::Remainder => left.wrapping_rem(right),
+                    BinaryOp::ShiftLeft => left << shift()?,
+                    BinaryOp::ShiftRight => left >> shift()?,
+                    BinaryOp::And => left & right,
+                    BinaryOp::Xor => left ^ right,
+                    BinaryOp::Or => left | right,
+                })
+            }
+        }
+    }
+
+    /// replaces the constants by their definitions,
+    /// leaving only labels and `$` to be evaluated later
+    pub fn substitute(&self, constants: &HashMap<String, Constant>) -> Result<Expr, EvalError> {
+        self.substitute_nested(constants, &mut vec![])
+    }
+
+    fn substitute_nested<'c>(
+        &self,
+        constants: &'c HashMap<String, Constant>,
+        resolving: &mut Vec<&'c str>,
+    ) -> Result<Expr, EvalError> {
+        Ok(match self {
+            Expr::Number(_) | Expr::Location(_) => self.clone(),
+            Expr::Symbol(name, span) => {
+                let Some((name, constant)) = constants.get_key_value(name) else {
+                    return Ok(self.clone());
+                };
+                if resolving.contains(&name.as_str()) {
+                    return Err(EvalError::Recursive(name.to_owned(), *span));
+                }
+                resolving.push(name);
+                let value = constant.value.substitute_nested(constants, resolving);
+                resolving.pop();
+                value?
+            }
+            Expr::Unary(op, operand) => Expr::Unary(
+                *op,
+                Box::new(operand.substitute_nested(constants, resolving)?),
+            ),
+            Expr::Binary(op, left, right, span) => Expr::Binary(
+                *op,
+                Box::new(left.substitute_nested(constants, resolving)?),
+                Box::new(right.substitute_nested(constants, resolving)?),
+                *span,
+            ),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{BinaryOp, Constant, EvalError, Expr, Scope, UnaryOp};
+    use crate::parser::source::Span;
+
+    fn symbol(name: &str) -> Expr {
+        Expr::Symbol(name.to_owned(), Span::default())
+    }
+
+    fn binary(op: BinaryOp, left: Expr, right: Expr) -> Expr {
+        Expr::Binary(op, Box::new(left), Box::new(right), Span::default())
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let mut constants = HashMap::new();
+        let mut define = |name: &str, value| {
+            let span = Span::default();
+            constants.insert(name.to_owned(), Constant { value, span });
+        };
+        define("SIZE", Expr::Number(16));
+        define(
+            "LAST",
+            binary(BinaryOp::Subtract, symbol("SIZE"), Expr::Number(1)),
+        );
+        define("LOOP", symbol("LOOP_AGAIN"));
+        define("LOOP_AGAIN", symbol("LOOP"));
+        let label = |name: &str| (name == "table").then_some(0x3000);
+        let scope = Scope {
+            constants: &constants,
+            label: &label,
+            location: None,
+        };
+
+        assert_eq!(symbol("LAST").evaluate(&scope), Ok(15));
+        let expr = binary(
+            BinaryOp::Or,
+            binary(BinaryOp::ShiftLeft, Expr::Number(0x10), Expr::Number(2)),
+            Expr::Unary(UnaryOp::Negate, Box::new(Expr::Number(1))),
+        );
+        assert_eq!(expr.evaluate(&scope), Ok(-1));
+        let expr = binary(BinaryOp::Add, symbol("table"), symbol("SIZE"));
+        assert_eq!(expr.evaluate(&scope), Ok(0x3010));
+        assert_eq!(
+            symbol("WIDTH").evaluate(&scope),
+            Err(EvalError::Undefined("WIDTH".to_owned(), Span::default()))
+        );
+        assert!(matches!(
+            symbol("LOOP").evaluate(&scope),
+            Err(EvalError::Recursive(name, _)) if name == "LOOP"
+        ));
+        let expr = binary(BinaryOp::Divide, Expr::Number(1), Expr::Number(0));
+        assert!(matches!(
+            expr.evaluate(&scope),
+            Err(EvalError::DivisionByZero(_))
+        ));
+        let expr = binary(BinaryOp::Remainder, Expr::Number(1), Expr::Number(0));
+        assert!(matches!(
+            expr.evaluate(&scope),
+            Err(EvalError::DivisionByZero(_))
+        ));
+        let expr = binary(BinaryOp::Divide, Expr::Number(i32::MIN), Expr::Number(-1));
+        assert!(matches!(expr.evaluate(&scope), Err(EvalError::Overflow(_))));
+        let expr = binary(
+            BinaryOp::Remainder,
+            Expr::Number(i32::MIN),
+            Expr::Number(-1),
+        );
+        assert_eq!(expr.evaluate(&scope), Ok(0));
+        assert!(matches!(
+            Expr::Location(Span::default()).evaluate(&scope),
+            Err(EvalError::UnknownLocation(_))
+        ));
+    }
+
+    #[test]
+    fn test_substitute() {
+        let mut constants = HashMap::new();
+        let span = Span::default();
+        let value = binary(BinaryOp::Add, symbol("start"), Expr::Number(2));
+        constants.insert("ENTRY".to_owned(), Constant { value, span });
+
+        let expr = binary(BinaryOp::Subtract, symbol("end"), symbol("ENTRY"));
+        let substituted = expr.substitute(&constants).unwrap();
+        assert_eq!(substituted.to_string(), "end - (start + #2)");
+    }
+}
@@ -0,0 +1,106 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use super::source::{FileId, Sources};
+
+/// finds and reads the files named by `.INCLUDE`
+pub trait Loader {
+    /// returns the id and text of the file `path` included by the file `from`,
+    /// or `None` if there is no such file.
+    /// loading the same file again has to return the same id
+    fn load(&mut self, path: &str, from: FileId) -> Option<(FileId, String)>;
+
+    /// records that `file` is translated, returning whether it has not been before.
+    /// shared by all translation units, so that a file given on the command line
+    /// and named by `.INCLUDE` is translated only once
+    fn translate_once(&mut self, file: FileId) -> bool;
+}
+
+/// a [`Loader`] reading from the file system, registering every file it reads in its [`Sources`]
+#[derive(Debug, Default)]
+pub struct FileLoader {
+    sources: Sources,
+    /// directories searched after the one of the including file
+    search_paths: Vec<PathBuf>,
+    /// ids of the files read so far, by their canonical path
+    ids: HashMap<PathBuf, FileId>,
+    /// the files translated so far, as translation units or by `.INCLUDE`
+    translated: HashSet<FileId>,
+}
+
+impl FileLoader {
+    pub fn new(search_paths: Vec<PathBuf>) -> Self {
+        Self {
+            search_paths,
+            ..Self::default()
+        }
+    }
+
+    pub fn sources(&self) -> &Sources {
+        &self.sources
+    }
+
+    /// reads the file at `path`, or returns its id if it has been read before
+    pub fn add(&mut self, path: &Path) -> io::Result<FileId> {
+        let canonical = path.canonicalize()?;
+        if let Some(&file) = self.ids.get(&canonical) {
+            return Ok(file);
+        }
+        let text = fs::read_to_string(path)?;
+        let file = self.sources.add(&path.to_string_lossy(), text);
+        self.ids.insert(canonical, file);
+        Ok(file)
+    }
+}
+
+impl Loader for FileLoader {
+    fn load(&mut self, path: &str, from: FileId) -> Option<(FileId, String)> {
+        let including = Path::new(&self.sources.get(from)?.name);
+        let directory = including.parent().unwrap_or(Path::new("")).to_path_buf();
+        let found = std::iter::once(directory)
+            .chain(self.search_paths.iter().cloned())
+            .map(|directory| directory.join(path))
+            .find(|candidate| candidate.is_file())?;
+        let file = self.add(&found).ok()?;
+        Some((file, self.sources.get(file)?.text.clone()))
+    }
+
+    fn translate_once(&mut self, file: FileId) -> bool {
+        self.translated.insert(file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{FileLoader, Loader};
+
+    #[test]
+    fn test_load() {
+        let root = std::env::temp_dir().join(format!("chasm-include-{}", std::process::id()));
+        fs::create_dir_all(root.join("src/lib")).unwrap();
+        fs::create_dir_all(root.join("std")).unwrap();
+        fs::write(root.join("src/main.asm"), ".INCLUDE \"lib/io.asm\"\n").unwrap();
+        fs::write(root.join("src/lib/io.asm"), "PUTS\n").unwrap();
+        fs::write(root.join("std/io.asm"), "OUT\n").unwrap();
+        fs::write(root.join("std/math.asm"), "RET\n").unwrap();
+
+        let mut loader = FileLoader::new(vec![root.join("std")]);
+        let main = loader.add(&root.join("src/main.asm")).unwrap();
+        let (io, text) = loader.load("lib/io.asm", main).unwrap();
+        assert_eq!(text, "PUTS\n");
+        let (math, text) = loader.load("math.asm", io).unwrap();
+        assert_eq!(text, "RET\n");
+        assert_eq!(loader.load("../src/lib/io.asm", math).unwrap().0, io);
+        assert_eq!(loader.load("missing.asm", main), None);
+        assert!(loader.translate_once(io));
+        assert_eq!(loader.add(&root.join("src/lib/io.asm")).unwrap(), io);
+        assert!(!loader.translate_once(io));
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,118 @@
+use std::collections::HashSet;
+
+use super::{scanner::LexError, source::Span, tokenizer::Token};
+
+/// a macro defined with `.MACRO name param, ...` up to `.ENDM`
+#[derive(Debug)]
+pub struct Macro {
+    pub params: Vec<String>,
+    /// the lines between `.MACRO` and `.ENDM` with their positions, ending with a line break
+    pub body: Vec<(Result<Token, LexError>, Span)>,
+    /// where the macro is defined
+    pub span: Span,
+}
+
+/// whether `label` is a local label renamed by [`Macro::expand`],
+/// which labels written in the source cannot be, as they do not contain a `.`
+pub fn is_expanded_local(label: &str) -> bool {
+    label.contains('.')
+}
+
+impl Macro {
+    /// the labels defined in the body, which every expansion gets its own copy of
+    fn local_labels(&self) -> HashSet<&str> {
+        self.body
+            .windows(2)
+            .filter_map(|pair| match pair {
+                [(Ok(Token::Word(label)), _), (Ok(Token::Colon), _)] => Some(label.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// the body with `args` in place of the parameters,
+    /// and the local labels renamed to `label.suffix`
+    pub fn expand(
+        &self,
+        args: &[Vec<Token>],
+        suffix: &str,
+    ) -> Vec<(Result<Token, LexError>, Span)> {
+        let locals = self.local_labels();
+        let mut tokens = vec![];
+        for (token, span) in &self.body {
+            let Ok(Token::Word(word)) = token else {
+                tokens.push((token.clone(), *span));
+                continue;
+            };
+            if let Some(index) = self.params.iter().position(|param| param == word) {
+                tokens.extend(args[index].iter().map(|arg| (Ok(arg.clone()), *span)));
+            } else if locals.contains(word.as_str()) {
+                tokens.push((Ok(Token::Word(format!("{word}.{suffix}"))), *span));
+            } else {
+                tokens.push((token.clone(), *span));
+            }
+        }
+        tokens
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Macro;
+    use crate::{
+        lc_3::registers::Registers,
+        parser::{
+            source::Span,
+            tokenizer::{Token, Tokenizer},
+        },
+    };
+
+    #[test]
+    fn test_expand() {
+        let text = "ADD reg, reg, #-1\nBRp again\nagain: BR target\n";
+        let body = Tokenizer::new(text)
+            .map(|token| (token, Span::default()))
+            .collect();
+        let macro_ = Macro {
+            params: vec!["reg".to_owned(), "target".to_owned()],
+            body,
+            span: Span::default(),
+        };
+        let args = [
+            vec![Token::Register(Registers::R1)],
+            vec![
+                Token::Word("table".to_owned()),
+                Token::Plus,
+                Token::Number(2),
+            ],
+        ];
+        let tokens: Vec<_> = macro_
+            .expand(&args, "0.1")
+            .into_iter()
+            .map(|(token, _)| token.unwrap())
+            .collect();
+        use Token::*;
+        assert_eq!(
+            tokens,
+            [
+                Add,
+                Register(Registers::R1),
+                Comma,
+                Register(Registers::R1),
+                Comma,
+                Number(-1),
+                Linebreak,
+                Br(false, false, true),
+                Word("again.0.1".to_owned()),
+                Linebreak,
+                Word("again.0.1".to_owned()),
+                Colon,
+                Br(true, true, true),
+                Word("table".to_owned()),
+                Plus,
+                Number(2),
+                Linebreak,
+            ]
+        );
+    }
+}
@@ -1,3 +1,10 @@
+pub mod diagnostic;
+pub mod expr;
+pub mod include;
+pub mod macros;
 pub mod scanner;
+pub mod source;
+pub mod suggest;
 pub mod tokenizer;
 pub mod translator;
+pub mod warning;
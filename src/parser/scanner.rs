@@ -1,124 +1,285 @@
-use std::{iter::Peekable, str::Bytes};
+use std::fmt;
+
+use super::source::{FileId, Span};
+
+/// malformed input the [`Scanner`] cannot split into items
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LexError {
+    /// a string literal is missing its closing `"` on the same line
+    UnclosedString,
+    /// a `"` in the middle of a word
+    UnexpectedQuote,
+    /// a `\` at the very end of the input
+    TrailingEscape,
+    /// a `\x` escape not followed by two hex digits
+    InvalidHexEscape,
+    /// a character literal is missing its closing `'` on the same line
+    UnclosedCharacter,
+    /// a character literal like `''` or `'ab'` that is not exactly one byte
+    InvalidCharacter,
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LexError::UnclosedString => write!(f, "unclosed string"),
+            LexError::UnexpectedQuote => write!(f, "unexpected '\"'"),
+            LexError::TrailingEscape => write!(f, "escaping nothing"),
+            LexError::InvalidHexEscape => write!(f, "\\x expects 2 hex digits"),
+            LexError::UnclosedCharacter => write!(f, "unclosed character literal"),
+            LexError::InvalidCharacter => write!(f, "expected exactly one character"),
+        }
+    }
+}
 
 pub struct Scanner<'a> {
-    text: Peekable<Bytes<'a>>,
-    previous: Option<u8>,
+    text: &'a [u8],
+    position: usize,
+    file: FileId,
+    line: u32,
+    line_start: usize,
+    span: Span,
 }
 
 impl<'a> Scanner<'a> {
     pub fn new(text: &'a str) -> Self {
+        Self::with_file(text, 0)
+    }
+
+    pub fn with_file(text: &'a str, file: FileId) -> Self {
         Self {
-            text: text.bytes().peekable(),
-            previous: None,
+            text: text.as_bytes(),
+            position: 0,
+            file,
+            line: 1,
+            line_start: 0,
+            span: Span {
+                file,
+                line: 1,
+                column: 1,
+                start: 0,
+                end: 0,
+            },
         }
     }
-}
 
-fn get_hex(text: &mut Peekable<Bytes<'_>>) -> Option<u8> {
-    let mut num = String::new();
-    if let Some(next) = text.next() {
-        num.push(next as char);
-    } else {
-        panic!("\\x expects value")
+    /// position of the item last returned by `next`
+    pub fn span(&self) -> Span {
+        self.span
     }
-    if let Some(next) = text.next() {
-        num.push(next as char);
-    } else {
-        panic!("\\x expects 2 values")
+
+    /// skips the rest of the line including its line break without splitting it into items,
+    /// returning the skipped text, or `None` at the end of the input
+    pub fn skip_line(&mut self) -> Option<&'a [u8]> {
+        self.peek()?;
+        self.begin();
+        let start = self.position;
+        while let Some(ch) = self.bump() {
+            if ch == b'\n' {
+                break;
+            }
+        }
+        self.span.end = self.position;
+        Some(&self.text[start..self.position])
     }
 
-    if let Ok(num) = u8::from_str_radix(num.as_str(), 16) {
-        Some(num)
-    } else {
-        None
+    fn peek(&self) -> Option<u8> {
+        self.text.get(self.position).copied()
     }
-}
 
-impl<'a> Iterator for Scanner<'a> {
-    type Item = Vec<u8>;
+    fn peek_second(&self) -> Option<u8> {
+        self.text.get(self.position + 1).copied()
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if matches!(self.text.peek(), None) && matches!(self.previous, None) {
+    fn bump(&mut self) -> Option<u8> {
+        let ch = self.peek()?;
+        self.position += 1;
+        if ch == b'\n' {
+            self.line += 1;
+            self.line_start = self.position;
+        }
+        Some(ch)
+    }
+
+    fn begin(&mut self) {
+        self.span = Span {
+            file: self.file,
+            line: self.line,
+            column: (self.position - self.line_start + 1) as u32,
+            start: self.position,
+            end: self.position,
+        };
+    }
+
+    fn finish(&mut self, item: Result<Vec<u8>, LexError>) -> Option<<Self as Iterator>::Item> {
+        self.span.end = self.position;
+        Some(item)
+    }
+
+    fn at_line_end(&self) -> bool {
+        match self.peek() {
+            None | Some(b'\n') => true,
+            Some(b'\r') => self.peek_second() == Some(b'\n'),
+            _ => false,
+        }
+    }
+
+    /// reads the two hex digits of a `\x` escape
+    fn get_hex(&mut self) -> Option<u8> {
+        let digits = self.text.get(self.position..self.position + 2)?;
+        if !digits.iter().all(u8::is_ascii_hexdigit) {
             return None;
         }
-        let mut buffer = vec![];
+        let num = u8::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()?;
+        self.bump();
+        self.bump();
+        Some(num)
+    }
 
-        if let Some(previous) = self.previous {
-            self.previous = None;
-            match previous {
-                b',' | b':' | b'.' | b';' | b'\n' => return Some(vec![previous]),
-                x => unreachable!("{x:?} should not be in this place"),
-            }
+    /// reads a string literal, which has to be closed on the same line
+    fn string(&mut self) -> Result<Vec<u8>, LexError> {
+        self.quoted(b'"', LexError::UnclosedString)
+    }
+
+    /// reads a character literal like `'a'` or `'\n'`, with the escapes of strings
+    fn character(&mut self) -> Result<Vec<u8>, LexError> {
+        let literal = self.quoted(b'\'', LexError::UnclosedCharacter)?;
+        if literal.len() != 3 {
+            return Err(LexError::InvalidCharacter);
         }
+        Ok(literal)
+    }
 
-        let mut in_str = false;
+    /// reads the text up to the closing `quote` on the same line, resolving escapes
+    fn quoted(&mut self, quote: u8, unclosed: LexError) -> Result<Vec<u8>, LexError> {
+        let mut buffer = vec![];
+        buffer.extend(self.bump());
+        let mut error = None;
 
-        while let Some(ch) = self.text.next() {
-            if !in_str {
-                match ch {
-                    b' ' | b'\t' => {
-                        if !buffer.is_empty() {
-                            return Some(buffer);
-                        }
-                    }
-                    b',' | b':' | b'.' | b';' | b'\n' => {
-                        if buffer.is_empty() {
-                            return Some(vec![ch]);
-                        } else {
-                            self.previous = Some(ch);
-                            return Some(buffer);
-                        }
-                    }
-                    b'\r' if matches!(self.text.peek(), Some(b'\n')) => {
-                        if buffer.is_empty() {
-                            return Some(vec![b'\n']);
-                        } else {
-                            self.previous = self.text.next();
-                            return Some(buffer);
-                        }
-                    }
-                    b'"' if buffer.is_empty() => {
-                        buffer.push(ch);
-                        in_str = true;
-                    }
-                    b'"' => panic!("unexpected '\"'"),
-
-                    ch => {
-                        buffer.push(ch);
-                    }
-                }
-            } else if ch == b'"' {
+        while !self.at_line_end() {
+            let ch = self.bump().unwrap();
+            if ch == quote {
                 buffer.push(ch);
-                return Some(buffer);
+                return error.map_or(Ok(buffer), Err);
             } else if ch == b'\\' {
-                if let Some(next) = self.text.next() {
-                    match next {
-                        b'n' => buffer.push(b'\n'),
-                        b't' => buffer.push(b'\t'),
-                        b'r' => buffer.push(b'\r'),
-                        b'x' => buffer
-                            .push(get_hex(&mut self.text).expect("couldnt convert hex literal")),
-                        x => buffer.push(x),
-                    }
-                } else {
-                    panic!("escaping nothing");
+                match self.bump() {
+                    Some(b'n') => buffer.push(b'\n'),
+                    Some(b't') => buffer.push(b'\t'),
+                    Some(b'r') => buffer.push(b'\r'),
+                    Some(b'x') => match self.get_hex() {
+                        Some(num) => buffer.push(num),
+                        None => {
+                            error.get_or_insert(LexError::InvalidHexEscape);
+                        }
+                    },
+                    Some(x) => buffer.push(x),
+                    None => return Err(LexError::TrailingEscape),
                 }
             } else {
                 buffer.push(ch);
             }
         }
 
-        if in_str {
-            panic!("unclosed string");
+        Err(error.unwrap_or(unclosed))
+    }
+}
+
+/// characters that always form an item on their own
+fn is_punctuation(ch: u8) -> bool {
+    matches!(
+        ch,
+        b',' | b':'
+            | b'.'
+            | b';'
+            | b'\n'
+            | b'='
+            | b'+'
+            | b'*'
+            | b'/'
+            | b'%'
+            | b'&'
+            | b'|'
+            | b'^'
+            | b'~'
+            | b'('
+            | b')'
+            | b'$'
+    )
+}
+
+/// prefixes after which a `-` is the sign of a number, as in `#-5` or `x-a`
+const SIGNED_PREFIXES: [&[u8]; 8] = [b"#", b"x", b"X", b"b", b"0x", b"0X", b"0b", b"0B"];
+
+impl<'a> Iterator for Scanner<'a> {
+    type Item = Result<Vec<u8>, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while matches!(self.peek(), Some(b' ' | b'\t')) {
+            self.bump();
         }
+        self.begin();
 
-        Some(buffer)
+        match self.peek()? {
+            ch if is_punctuation(ch) => {
+                self.bump();
+                return self.finish(Ok(vec![ch]));
+            }
+            ch @ (b'<' | b'>') => {
+                self.bump();
+                // shifts are written `<<` and `>>`
+                if self.peek() == Some(ch) {
+                    self.bump();
+                    return self.finish(Ok(vec![ch, ch]));
+                }
+                return self.finish(Ok(vec![ch]));
+            }
+            b'-' => {
+                self.bump();
+                return self.finish(Ok(vec![b'-']));
+            }
+            b'\r' if self.peek_second() == Some(b'\n') => {
+                self.bump();
+                self.bump();
+                return self.finish(Ok(vec![b'\n']));
+            }
+            b'"' => {
+                let string = self.string();
+                return self.finish(string);
+            }
+            b'\'' => {
+                let character = self.character();
+                return self.finish(character);
+            }
+            _ => {}
+        }
+
+        let mut buffer = vec![];
+        while let Some(ch) = self.peek() {
+            match ch {
+                b' ' | b'\t' | b'<' | b'>' => break,
+                ch if is_punctuation(ch) => break,
+                b'-' if !SIGNED_PREFIXES.contains(&buffer.as_slice()) => break,
+                b'\r' if self.peek_second() == Some(b'\n') => break,
+                b'"' => {
+                    self.begin();
+                    self.bump();
+                    return self.finish(Err(LexError::UnexpectedQuote));
+                }
+                ch => {
+                    self.bump();
+                    buffer.push(ch);
+                }
+            }
+        }
+
+        self.finish(Ok(buffer))
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Scanner;
+    use super::{LexError, Scanner};
+    use crate::parser::source::Span;
 
     macro_rules! str_vec {
         ($string:expr) => {{
@@ -131,22 +292,22 @@ mod tests {
     fn test_next() {
         let text = "hello this is test";
         let mut scanner = Scanner::new(text);
-        assert_eq!(scanner.next(), Some(str_vec!("hello")));
-        assert_eq!(scanner.next(), Some(str_vec!("this")));
-        assert_eq!(scanner.next(), Some(str_vec!("is")));
-        assert_eq!(scanner.next(), Some(str_vec!("test")));
+        assert_eq!(scanner.next(), Some(Ok(str_vec!("hello"))));
+        assert_eq!(scanner.next(), Some(Ok(str_vec!("this"))));
+        assert_eq!(scanner.next(), Some(Ok(str_vec!("is"))));
+        assert_eq!(scanner.next(), Some(Ok(str_vec!("test"))));
         assert_eq!(scanner.next(), None);
     }
     #[test]
     fn test_commas() {
         let text = "hello, this is : test";
         let mut scanner = Scanner::new(text);
-        assert_eq!(scanner.next(), Some(str_vec!("hello")));
-        assert_eq!(scanner.next(), Some(str_vec!(",")));
-        assert_eq!(scanner.next(), Some(str_vec!("this")));
-        assert_eq!(scanner.next(), Some(str_vec!("is")));
-        assert_eq!(scanner.next(), Some(str_vec!(":")));
-        assert_eq!(scanner.next(), Some(str_vec!("test")));
+        assert_eq!(scanner.next(), Some(Ok(str_vec!("hello"))));
+        assert_eq!(scanner.next(), Some(Ok(str_vec!(","))));
+        assert_eq!(scanner.next(), Some(Ok(str_vec!("this"))));
+        assert_eq!(scanner.next(), Some(Ok(str_vec!("is"))));
+        assert_eq!(scanner.next(), Some(Ok(str_vec!(":"))));
+        assert_eq!(scanner.next(), Some(Ok(str_vec!("test"))));
         assert_eq!(scanner.next(), None);
     }
 
@@ -154,9 +315,9 @@ mod tests {
     fn test_whitespace() {
         let text = "   \t\thello   \n\t \n";
         let mut scanner = Scanner::new(text);
-        assert_eq!(scanner.next(), Some(str_vec!("hello")));
-        assert_eq!(scanner.next(), Some(str_vec!("\n")));
-        assert_eq!(scanner.next(), Some(str_vec!("\n")));
+        assert_eq!(scanner.next(), Some(Ok(str_vec!("hello"))));
+        assert_eq!(scanner.next(), Some(Ok(str_vec!("\n"))));
+        assert_eq!(scanner.next(), Some(Ok(str_vec!("\n"))));
         assert_eq!(scanner.next(), None);
     }
 
@@ -164,13 +325,13 @@ mod tests {
     fn test_crlf() {
         let text = "XOR R0, R0, R0\r\n";
         let mut scanner = Scanner::new(text);
-        assert_eq!(scanner.next(), Some(str_vec!("XOR")));
-        assert_eq!(scanner.next(), Some(str_vec!("R0")));
-        assert_eq!(scanner.next(), Some(str_vec!(",")));
-        assert_eq!(scanner.next(), Some(str_vec!("R0")));
-        assert_eq!(scanner.next(), Some(str_vec!(",")));
-        assert_eq!(scanner.next(), Some(str_vec!("R0")));
-        assert_eq!(scanner.next(), Some(str_vec!("\n")));
+        assert_eq!(scanner.next(), Some(Ok(str_vec!("XOR"))));
+        assert_eq!(scanner.next(), Some(Ok(str_vec!("R0"))));
+        assert_eq!(scanner.next(), Some(Ok(str_vec!(","))));
+        assert_eq!(scanner.next(), Some(Ok(str_vec!("R0"))));
+        assert_eq!(scanner.next(), Some(Ok(str_vec!(","))));
+        assert_eq!(scanner.next(), Some(Ok(str_vec!("R0"))));
+        assert_eq!(scanner.next(), Some(Ok(str_vec!("\n"))));
         assert_eq!(scanner.next(), None);
     }
 
@@ -178,9 +339,91 @@ mod tests {
     fn test_escaped() {
         let text = "\"hello \\\" !!\"";
         let mut scanner = Scanner::new(text);
-        assert_eq!(scanner.next(), Some(str_vec!("\"hello \" !!\"")));
+        assert_eq!(scanner.next(), Some(Ok(str_vec!("\"hello \" !!\""))));
         let text = "\"\\x69\"";
         let mut scanner = Scanner::new(text);
-        assert_eq!(scanner.next(), Some(str_vec!("\"i\"")));
+        assert_eq!(scanner.next(), Some(Ok(str_vec!("\"i\""))));
+    }
+
+    #[test]
+    fn test_span() {
+        let text = "ADD R0,\r\n  label: \"a b\"";
+        let mut scanner = Scanner::with_file(text, 3);
+        scanner.next();
+        assert_eq!(scanner.span(), span(3, 1, 1, 0, 3));
+        scanner.next();
+        assert_eq!(scanner.span(), span(3, 1, 5, 4, 6));
+        scanner.next();
+        assert_eq!(scanner.span(), span(3, 1, 7, 6, 7));
+        assert_eq!(scanner.next(), Some(Ok(str_vec!("\n"))));
+        assert_eq!(scanner.span(), span(3, 1, 8, 7, 9));
+        assert_eq!(scanner.next(), Some(Ok(str_vec!("label"))));
+        assert_eq!(scanner.span(), span(3, 2, 3, 11, 16));
+        scanner.next();
+        assert_eq!(scanner.next(), Some(Ok(str_vec!("\"a b\""))));
+        assert_eq!(scanner.span(), span(3, 2, 10, 18, 23));
+        assert_eq!(scanner.next(), None);
+        assert_eq!(scanner.span(), span(3, 2, 15, 23, 23));
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut scanner = Scanner::new("DB \"abc\nDB \"\\x6\"\nab\"c \"\\");
+        assert_eq!(scanner.next(), Some(Ok(str_vec!("DB"))));
+        assert_eq!(scanner.next(), Some(Err(LexError::UnclosedString)));
+        assert_eq!(scanner.span(), span(0, 1, 4, 3, 7));
+        assert_eq!(scanner.next(), Some(Ok(str_vec!("\n"))));
+        assert_eq!(scanner.next(), Some(Ok(str_vec!("DB"))));
+        assert_eq!(scanner.next(), Some(Err(LexError::InvalidHexEscape)));
+        assert_eq!(scanner.next(), Some(Ok(str_vec!("\n"))));
+        assert_eq!(scanner.next(), Some(Err(LexError::UnexpectedQuote)));
+        assert_eq!(scanner.span(), span(0, 3, 3, 19, 20));
+        assert_eq!(scanner.next(), Some(Ok(str_vec!("c"))));
+        assert_eq!(scanner.next(), Some(Err(LexError::TrailingEscape)));
+        assert_eq!(scanner.next(), None);
+    }
+
+    fn span(file: usize, line: u32, column: u32, start: usize, end: usize) -> Span {
+        Span {
+            file,
+            line,
+            column,
+            start,
+            end,
+        }
+    }
+
+    #[test]
+    fn test_characters() {
+        let text = r#"'a' '\n' '\'' '"' '\x7e' don't '' 'ab' 'a"#;
+        let items: Vec<_> = Scanner::new(text).collect();
+        assert_eq!(
+            items,
+            [
+                Ok(str_vec!("'a'")),
+                Ok(str_vec!("'\n'")),
+                Ok(str_vec!("'''")),
+                Ok(str_vec!("'\"'")),
+                Ok(str_vec!("'~'")),
+                Ok(str_vec!("don't")),
+                Err(LexError::InvalidCharacter),
+                Err(LexError::InvalidCharacter),
+                Err(LexError::UnclosedCharacter),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_operators() {
+        let text = "(x10<<2)|SIZE-1 #-5 x-a -b1 $+4>>1";
+        let items: Vec<_> = Scanner::new(text).map(Result::unwrap).collect();
+        let expected = [
+            "(", "x10", "<<", "2", ")", "|", "SIZE", "-", "1", "#-5", "x-a", "-", "b1", "$", "+",
+            "4", ">>", "1",
+        ];
+        assert_eq!(
+            items,
+            expected.map(|item| item.as_bytes().to_vec()).to_vec()
+        );
     }
 }
@@ -0,0 +1,91 @@
+/// index of a file in [`Sources`]
+pub type FileId = usize;
+
+/// location of a token in a source file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub file: FileId,
+    /// line number, starting at 1
+    pub line: u32,
+    /// column in bytes, starting at 1
+    pub column: u32,
+    /// byte offset of the first byte
+    pub start: usize,
+    /// byte offset after the last byte
+    pub end: usize,
+}
+
+#[derive(Debug)]
+pub struct SourceFile {
+    pub name: String,
+    pub text: String,
+}
+
+/// all files taking part in an assembly, indexed by [`FileId`]
+#[derive(Debug, Default)]
+pub struct Sources {
+    files: Vec<SourceFile>,
+}
+
+impl Sources {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// registers a file and returns its id
+    pub fn add(&mut self, name: &str, text: String) -> FileId {
+        self.files.push(SourceFile {
+            name: name.to_owned(),
+            text,
+        });
+        self.files.len() - 1
+    }
+
+    pub fn get(&self, file: FileId) -> Option<&SourceFile> {
+        self.files.get(file)
+    }
+
+    /// returns the full line `span` starts in, without the line terminator
+    pub fn line(&self, span: Span) -> Option<&str> {
+        let text = &self.get(span.file)?.text;
+        let start = span.start.min(text.len());
+        let line_start = text[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = text[start..].find('\n').map_or(text.len(), |i| start + i);
+        Some(text[line_start..line_end].trim_end_matches('\r'))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Sources, Span};
+
+    #[test]
+    fn test_line() {
+        let mut sources = Sources::new();
+        let file = sources.add("main.asm", "ADD R0, R0, R1\r\nlabel:\nRET".to_owned());
+        let span = Span {
+            file,
+            line: 2,
+            column: 1,
+            start: 16,
+            end: 21,
+        };
+        assert_eq!(sources.line(span), Some("label:"));
+        let span = Span {
+            file,
+            line: 1,
+            column: 14,
+            start: 13,
+            end: 14,
+        };
+        assert_eq!(sources.line(span), Some("ADD R0, R0, R1"));
+        let span = Span {
+            file,
+            line: 3,
+            column: 4,
+            start: 26,
+            end: 26,
+        };
+        assert_eq!(sources.line(span), Some("RET"));
+    }
+}
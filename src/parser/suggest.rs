@@ -0,0 +1,64 @@
+/// number of single character insertions, deletions, substitutions
+/// and swaps of adjacent characters needed to turn `a` into `b`, ignoring case
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().map(|ch| ch.to_ascii_lowercase()).collect();
+    let b: Vec<char> = b.chars().map(|ch| ch.to_ascii_lowercase()).collect();
+
+    // distance[i][j] is the distance between the first i characters of `a` and j of `b`
+    let mut distance = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distance.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    distance[0] = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let substitution = distance[i - 1][j - 1] + usize::from(a[i - 1] != b[j - 1]);
+            let mut best = substitution
+                .min(distance[i - 1][j] + 1)
+                .min(distance[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(distance[i - 2][j - 2] + 1);
+            }
+            distance[i][j] = best;
+        }
+    }
+    distance[a.len()][b.len()]
+}
+
+/// the candidate closest to `word`, if any is close enough to be a likely typo
+pub fn closest<'a>(word: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    // allow one typo in short words and one more every three characters
+    let max_distance = 1.max(word.chars().count() / 3);
+    candidates
+        .into_iter()
+        .filter(|&candidate| candidate != word)
+        .map(|candidate| (edit_distance(word, candidate), candidate))
+        .filter(|&(distance, _)| distance <= max_distance)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, candidate)| candidate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{closest, edit_distance};
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("ADD", "ADD"), 0);
+        assert_eq!(edit_distance("ADDD", "ADD"), 1);
+        assert_eq!(edit_distance("LDR", "LDW"), 1);
+        assert_eq!(edit_distance("loop", "LOOP"), 0);
+        assert_eq!(edit_distance("lopo", "loop"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "RET"), 3);
+    }
+
+    #[test]
+    fn test_closest() {
+        let mnemonics = ["ADD", "AND", "LDB", "LDW", "RET"];
+        assert_eq!(closest("ADDD", mnemonics), Some("ADD"));
+        assert_eq!(closest("LDR", mnemonics), Some("LDB"));
+        assert_eq!(closest("add", mnemonics), Some("ADD"));
+        assert_eq!(closest("FOO", mnemonics), None);
+    }
+}
@@ -1,9 +1,12 @@
 use crate::lc_3::registers::Registers;
 use std::str::FromStr;
 
-use super::scanner::Scanner;
+use super::{
+    scanner::{LexError, Scanner},
+    source::{FileId, Span},
+};
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Clone)]
 pub enum Token {
     Add,
     And,
@@ -24,16 +27,23 @@ pub enum Token {
     Stw,
     Jsr,
     Jsrr,
+    // pseudo-instructions
+    Nop,
+    Mov,
+    Clr,
+    Neg,
+    Sub,
+    Inc,
+    Dec,
+    Push,
+    Pop,
+    Ldi16,
     // static memory layout
     DefineBytes,
+    /// `DB` with a terminating zero byte
+    DefineBytesZero,
     DefineWords,
     Trap,
-    // traps
-    Halt,
-    Getc,
-    Out,
-    Puts,
-    In,
     Register(Registers),
     Number(i32),
     Comma,
@@ -42,19 +52,219 @@ pub enum Token {
     Semicolon,
     Period,
     Equals,
+    // expressions
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Percent,
+    Ampersand,
+    Pipe,
+    Caret,
+    Tilde,
+    ShiftLeft,
+    ShiftRight,
+    LeftParen,
+    RightParen,
+    /// `$`, the address of the current statement
+    Dollar,
     Word(String),
     Str(String),
 }
 
+/// every mnemonic the tokenizer recognizes, used to suggest corrections for unknown ones
+pub const MNEMONICS: [&str; 39] = [
+    "ADD", "AND", "XOR", "NOT", "JMP", "RET", "BR", "BRn", "BRnp", "BRnz", "BRzp", "BRnzp", "BRz",
+    "BRp", "LSHF", "RSHFL", "RSHFA", "LEA", "RTI", "TRAP", "STB", "STW", "LDB", "LDW", "JSR",
+    "JSRR", "NOP", "MOV", "CLR", "NEG", "SUB", "INC", "DEC", "PUSH", "POP", "LDI16", "DB", "DBZ",
+    "DW",
+];
+
+/// the named traps known before any `.TRAPDEF`, with their vectors.
+/// they are read as words, as the translator decides which words name traps
+pub const TRAPS: [(&str, u8); 5] = [
+    ("GETC", 0x20),
+    ("OUT", 0x21),
+    ("PUTS", 0x22),
+    ("IN", 0x23),
+    ("HALT", HALT_VECTOR),
+];
+
+/// the trap vector that stops the machine, after which execution does not continue
+pub const HALT_VECTOR: u8 = 0x25;
+
 pub struct Tokenizer<'a> {
     scanner: Scanner<'a>,
+    line_start: bool,
+    classic: bool,
+    span: Span,
+    /// a token read ahead by `peek`, with its span
+    peeked: Option<(Option<Result<Token, LexError>>, Span)>,
+    /// the macro expansions and included files being read, innermost last
+    expansions: Vec<Expansion>,
+}
+
+/// the tokens of a macro expansion or an included file, read before the rest of the input
+struct Expansion {
+    kind: ExpansionKind,
+    /// where the macro is used or the file is included
+    call: Span,
+    tokens: std::vec::IntoIter<(Result<Token, LexError>, Span)>,
+}
+
+enum ExpansionKind {
+    /// the name of the macro
+    Macro(String),
+    Include(FileId),
 }
 
 impl<'a> Tokenizer<'a> {
     pub fn new(text: &'a str) -> Self {
+        Self::with_file(text, 0)
+    }
+
+    pub fn with_file(text: &'a str, file: FileId) -> Self {
         Self {
-            scanner: Scanner::new(text),
+            scanner: Scanner::with_file(text, file),
+            line_start: true,
+            classic: false,
+            span: Span {
+                file,
+                line: 1,
+                column: 1,
+                start: 0,
+                end: 0,
+            },
+            peeked: None,
+            expansions: vec![],
+        }
+    }
+
+    /// switches on the syntax of classic LC-3 tools:
+    /// case-insensitive mnemonics and registers, BR flags in any order,
+    /// `0x`/`0b` prefixes, bare decimals and `_` as digit separator
+    pub fn classic(mut self, classic: bool) -> Self {
+        self.classic = classic;
+        self
+    }
+
+    /// position of the token last returned by `next`
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    /// returns the token `next` will return, without consuming it
+    pub fn peek(&mut self) -> Option<&Result<Token, LexError>> {
+        if self.peeked.is_none() {
+            self.peeked = Some(self.read_expanded());
+        }
+        self.peeked.as_ref().and_then(|(token, _)| token.as_ref())
+    }
+
+    /// whether the last token was a [`Token::Linebreak`] or nothing has been read yet
+    pub fn at_line_start(&self) -> bool {
+        self.line_start
+    }
+
+    /// reads `tokens`, the expansion of the macro `name` used at `call`,
+    /// before the rest of the input. must not be called while a token is peeked
+    pub fn expand(
+        &mut self,
+        name: String,
+        call: Span,
+        tokens: Vec<(Result<Token, LexError>, Span)>,
+    ) {
+        debug_assert!(self.peeked.is_none());
+        self.expansions.push(Expansion {
+            kind: ExpansionKind::Macro(name),
+            call,
+            tokens: tokens.into_iter(),
+        });
+    }
+
+    /// reads the tokens of `text`, the file `file` included at `call`,
+    /// before the rest of the input. must not be called while a token is peeked
+    pub fn include(&mut self, file: FileId, text: &str, call: Span) {
+        debug_assert!(self.peeked.is_none());
+        let mut included = Tokenizer::with_file(text, file).classic(self.classic);
+        let mut tokens = vec![];
+        while let Some(token) = included.next() {
+            tokens.push((token, included.span()));
+        }
+        // the last line of the file must not continue on the line after `.INCLUDE`
+        if !included.at_line_start() {
+            tokens.push((Ok(Token::Linebreak), included.span()));
         }
+        self.expansions.push(Expansion {
+            kind: ExpansionKind::Include(file),
+            call,
+            tokens: tokens.into_iter(),
+        });
+    }
+
+    /// skips the rest of the current line without turning it into tokens,
+    /// so that it cannot cause errors. returns the directive the line starts with in upper case,
+    /// like `.ENDIF` for `.endif`, an empty string if there is none,
+    /// or `None` at the end of the input. must not be called while a token is peeked
+    pub fn skip_line(&mut self) -> Option<String> {
+        debug_assert!(self.peeked.is_none());
+        self.line_start = true;
+        while let Some(expansion) = self.expansions.last_mut() {
+            if expansion.tokens.len() == 0 {
+                self.expansions.pop();
+                continue;
+            }
+            let mut line = vec![];
+            for (token, span) in expansion.tokens.by_ref() {
+                self.span = span;
+                match token {
+                    Ok(Token::Linebreak) => break,
+                    token => line.push(token),
+                }
+            }
+            return Some(match line.as_slice() {
+                [Ok(Token::Period), Ok(Token::Word(name)), ..] => {
+                    format!(".{}", name.to_ascii_uppercase())
+                }
+                _ => String::new(),
+            });
+        }
+
+        let line = self.scanner.skip_line()?;
+        self.span = self.scanner.span();
+        let line = line.trim_ascii_start();
+        let Some(name) = line.strip_prefix(b".") else {
+            return Some(String::new());
+        };
+        let name: String = name
+            .iter()
+            .take_while(|ch| ch.is_ascii_alphanumeric() || **ch == b'_')
+            .map(|&ch| ch.to_ascii_uppercase() as char)
+            .collect();
+        Some(format!(".{name}"))
+    }
+
+    /// the names and call sites of the macro expansions the last token is part of,
+    /// innermost first
+    pub fn expansions(&self) -> impl Iterator<Item = (&str, Span)> {
+        self.expansions
+            .iter()
+            .rev()
+            .filter_map(|expansion| match &expansion.kind {
+                ExpansionKind::Macro(name) => Some((name.as_str(), expansion.call)),
+                ExpansionKind::Include(_) => None,
+            })
+    }
+
+    /// the files being included, innermost first
+    pub fn includes(&self) -> impl Iterator<Item = FileId> + '_ {
+        self.expansions
+            .iter()
+            .rev()
+            .filter_map(|expansion| match expansion.kind {
+                ExpansionKind::Include(file) => Some(file),
+                ExpansionKind::Macro(_) => None,
+            })
     }
 }
 
@@ -80,65 +290,199 @@ fn is_int(int_str: &str, value: &mut Option<i32>) -> bool {
 }
 
 impl<'a> Iterator for Tokenizer<'a> {
-    type Item = Token;
+    type Item = Result<Token, LexError>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        let token = match self.peeked.take() {
+            Some((token, span)) => {
+                self.span = span;
+                token
+            }
+            None => {
+                let (token, span) = self.read_expanded();
+                self.span = span;
+                token
+            }
+        };
+        if let Some(token) = &token {
+            self.line_start = matches!(token, Ok(Token::Linebreak));
+        }
+        token
+    }
+}
+
+impl<'a> Tokenizer<'a> {
+    /// returns the next token of the innermost unfinished macro expansion,
+    /// or of the scanner if there is none
+    fn read_expanded(&mut self) -> (Option<Result<Token, LexError>>, Span) {
+        while let Some(expansion) = self.expansions.last_mut() {
+            if let Some((token, span)) = expansion.tokens.next() {
+                return (Some(token), span);
+            }
+            self.expansions.pop();
+        }
+        let token = self.read();
+        (token, self.scanner.span())
+    }
+
+    /// turns the next item of the scanner into a token
+    fn read(&mut self) -> Option<Result<Token, LexError>> {
         use Token::*;
-        if let Some(next) = self.scanner.next() {
-            let mut value = None;
-            let mut str_value = None;
-            let next: String = next.iter().map(|&ch| ch as char).collect::<String>();
-            match next.as_str() {
-                "ADD" => Some(Add),
-                "AND" => Some(And),
-                "XOR" => Some(Xor),
-                "NOT" => Some(Not),
-                "JMP" => Some(Jmp),
-                "RET" => Some(Ret),
-                "BR" => Some(Br(true, true, true)),
-                "BRn" => Some(Br(true, false, false)),
-                "BRnp" => Some(Br(true, false, true)),
-                "BRnz" => Some(Br(true, true, false)),
-                "BRzp" => Some(Br(false, true, true)),
-                "BRnzp" => Some(Br(true, true, true)),
-                "BRz" => Some(Br(false, true, false)),
-                "BRp" => Some(Br(false, false, true)),
-                "LSHF" => Some(Lshf),
-                "RSHFL" => Some(Rshfl),
-                "RSHFA" => Some(Rshfa),
-                "LEA" => Some(Lea),
-                "RTI" => Some(Rti),
-                // trap
-                "TRAP" => Some(Trap),
-                "HALT" => Some(Halt),
-                "GETC" => Some(Getc),
-                "OUT" => Some(Out),
-                "PUTS" => Some(Puts),
-                "IN" => Some(In),
-                "STB" => Some(Stb),
-                "STW" => Some(Stw),
-                "LDB" => Some(Ldb),
-                "LDW" => Some(Ldw),
-                "JSR" => Some(Jsr),
-                "JSRR" => Some(Jsrr),
-                "DB" => Some(DefineBytes),
-                "DW" => Some(DefineWords),
-                "," => Some(Comma),
-                "." => Some(Period),
-                "=" => Some(Equals),
-                "R0" | "R1" | "R2" | "R3" | "R4" | "R5" | "R6" | "R7" => {
-                    Some(Register(Registers::from_str(next.as_str()).unwrap()))
-                }
-                "\n" => Some(Linebreak),
-                ";" => Some(Semicolon),
-                ":" => Some(Colon),
-                x if is_int(x, &mut value) => Some(Number(value.unwrap())),
-                x if is_str(x, &mut str_value) => Some(Str(str_value.unwrap())),
-                x => Some(Word(x.to_owned())),
+        let next = match self.scanner.next()? {
+            Ok(next) => next,
+            Err(error) => return Some(Err(error)),
+        };
+        let mut value = None;
+        let mut str_value = None;
+        let mut next: String = next.iter().map(|&ch| ch as char).collect::<String>();
+        if self.classic {
+            if let Some(number) = classic_number(&next) {
+                return Some(Ok(Number(number)));
+            }
+            if let Some(keyword) = classic_keyword(&next) {
+                next = keyword;
+            }
+        }
+        let token = match next.as_str() {
+            "ADD" => Add,
+            "AND" => And,
+            "XOR" => Xor,
+            "NOT" => Not,
+            "JMP" => Jmp,
+            "RET" => Ret,
+            "BR" => Br(true, true, true),
+            "BRn" => Br(true, false, false),
+            "BRnp" => Br(true, false, true),
+            "BRnz" => Br(true, true, false),
+            "BRzp" => Br(false, true, true),
+            "BRnzp" => Br(true, true, true),
+            "BRz" => Br(false, true, false),
+            "BRp" => Br(false, false, true),
+            "LSHF" => Lshf,
+            "RSHFL" => Rshfl,
+            "RSHFA" => Rshfa,
+            "LEA" => Lea,
+            "RTI" => Rti,
+            // trap
+            "TRAP" => Trap,
+            "STB" => Stb,
+            "STW" => Stw,
+            "LDB" => Ldb,
+            "LDW" => Ldw,
+            "JSR" => Jsr,
+            "JSRR" => Jsrr,
+            // pseudo-instructions
+            "NOP" => Nop,
+            "MOV" => Mov,
+            "CLR" => Clr,
+            "NEG" => Neg,
+            "SUB" => Sub,
+            "INC" => Inc,
+            "DEC" => Dec,
+            "PUSH" => Push,
+            "POP" => Pop,
+            "LDI16" => Ldi16,
+            "DB" => DefineBytes,
+            "DBZ" => DefineBytesZero,
+            "DW" => DefineWords,
+            "," => Comma,
+            "." => Period,
+            "=" => Equals,
+            "+" => Plus,
+            "-" => Minus,
+            "*" => Star,
+            "/" => Slash,
+            "%" => Percent,
+            "&" => Ampersand,
+            "|" => Pipe,
+            "^" => Caret,
+            "~" => Tilde,
+            "<<" => ShiftLeft,
+            ">>" => ShiftRight,
+            "(" => LeftParen,
+            ")" => RightParen,
+            "$" => Dollar,
+            "R0" | "R1" | "R2" | "R3" | "R4" | "R5" | "R6" | "R7" => {
+                Register(Registers::from_str(next.as_str()).unwrap())
+            }
+            "\n" => Linebreak,
+            ";" => Semicolon,
+            ":" => Colon,
+            x if is_int(x, &mut value) => Number(value.unwrap()),
+            x if is_char(x, &mut value) => Number(value.unwrap()),
+            x if is_str(x, &mut str_value) => Str(str_value.unwrap()),
+            x => Word(x.to_owned()),
+        };
+        Some(Ok(token))
+    }
+}
+
+/// parses the number literals of classic LC-3 tools,
+/// like `#-5`, `x1F`, `X1F`, `0x1F`, `b101`, `0b101` and `1_000`.
+/// a `-` in front of a bare number is read as an operator instead
+fn classic_number(word: &str) -> Option<i32> {
+    let (radix, digits) = if let Some(digits) = word.strip_prefix('#') {
+        (10, digits)
+    } else if let Some(digits) = ["0x", "0X", "x", "X"]
+        .iter()
+        .find_map(|prefix| word.strip_prefix(prefix))
+    {
+        (16, digits)
+    } else if let Some(digits) = ["0b", "0B", "b"]
+        .iter()
+        .find_map(|prefix| word.strip_prefix(prefix))
+    {
+        (2, digits)
+    } else {
+        (10, word)
+    };
+    // a sign after the prefix, as in `#-5`
+    let (sign, digits) = match digits.strip_prefix('-') {
+        Some(digits) => (-1, digits),
+        None => (1, digits),
+    };
+
+    let separated = digits.starts_with('_') || digits.ends_with('_');
+    if digits.is_empty() || separated || !digits.chars().all(|ch| ch == '_' || ch.is_digit(radix)) {
+        return None;
+    }
+    let digits: String = digits.chars().filter(|&ch| ch != '_').collect();
+    i32::from_str_radix(&digits, radix)
+        .ok()
+        .map(|value| sign * value)
+}
+
+/// the spelling the keyword table uses for a mnemonic or register in any case,
+/// with the flags of `BR` in any order
+fn classic_keyword(word: &str) -> Option<String> {
+    let upper = word.to_ascii_uppercase();
+    if let Some(flags) = upper.strip_prefix("BR") {
+        let flags = flags.as_bytes();
+        let mut canonical = "BR".to_owned();
+        for flag in [b'N', b'Z', b'P'] {
+            match flags.iter().filter(|&&ch| ch == flag).count() {
+                0 => {}
+                1 => canonical.push(flag.to_ascii_lowercase() as char),
+                _ => return None,
             }
-        } else {
-            None
         }
+        return (canonical.len() == flags.len() + 2).then_some(canonical);
+    }
+
+    let register = matches!(upper.as_bytes(), [b'R', b'0'..=b'7']);
+    let trap = TRAPS.iter().any(|&(name, _)| name == upper);
+    (register || trap || MNEMONICS.contains(&upper.as_str())).then_some(upper)
+}
+
+/// returns whether `maybe_char` is a character literal like `'a'` and if so, sets value
+fn is_char(maybe_char: &str, value: &mut Option<i32>) -> bool {
+    let mut chars = maybe_char.chars();
+    match (chars.next(), chars.next(), chars.next(), chars.next()) {
+        (Some('\''), Some(ch), Some('\''), None) => {
+            *value = Some(ch as i32);
+            true
+        }
+        _ => false,
     }
 }
 
@@ -161,11 +505,11 @@ mod tests {
     fn test_1() {
         let text = "ADD R1, #10\n";
         let mut tokenizer = Tokenizer::new(text);
-        assert_eq!(tokenizer.next(), Some(Token::Add));
-        assert_eq!(tokenizer.next(), Some(Token::Register(Registers::R1)));
-        assert_eq!(tokenizer.next(), Some(Token::Comma));
-        assert_eq!(tokenizer.next(), Some(Token::Number(10)));
-        assert_eq!(tokenizer.next(), Some(Token::Linebreak));
+        assert_eq!(tokenizer.next(), Some(Ok(Token::Add)));
+        assert_eq!(tokenizer.next(), Some(Ok(Token::Register(Registers::R1))));
+        assert_eq!(tokenizer.next(), Some(Ok(Token::Comma)));
+        assert_eq!(tokenizer.next(), Some(Ok(Token::Number(10))));
+        assert_eq!(tokenizer.next(), Some(Ok(Token::Linebreak)));
         assert_eq!(tokenizer.next(), None);
     }
 
@@ -174,21 +518,122 @@ mod tests {
         use super::Token::*;
         let text = "ADD\nADD R1,R0\n; Comment b1010\nADD R0, x-a";
         let mut tokenizer = Tokenizer::new(text);
-        assert_eq!(tokenizer.next(), Some(Add));
-        assert_eq!(tokenizer.next(), Some(Linebreak));
-        assert_eq!(tokenizer.next(), Some(Add));
-        assert_eq!(tokenizer.next(), Some(Register(Registers::R1)));
-        assert_eq!(tokenizer.next(), Some(Comma));
-        assert_eq!(tokenizer.next(), Some(Register(Registers::R0)));
-        assert_eq!(tokenizer.next(), Some(Linebreak));
-        assert_eq!(tokenizer.next(), Some(Semicolon));
-        assert_eq!(tokenizer.next(), Some(Word("Comment".to_owned())));
-        assert_eq!(tokenizer.next(), Some(Number(0b1010)));
-        assert_eq!(tokenizer.next(), Some(Linebreak));
-        assert_eq!(tokenizer.next(), Some(Add));
-        assert_eq!(tokenizer.next(), Some(Register(Registers::R0)));
-        assert_eq!(tokenizer.next(), Some(Comma));
-        assert_eq!(tokenizer.next(), Some(Number(-10)));
+        assert_eq!(tokenizer.next(), Some(Ok(Add)));
+        assert_eq!(tokenizer.next(), Some(Ok(Linebreak)));
+        assert_eq!(tokenizer.next(), Some(Ok(Add)));
+        assert_eq!(tokenizer.next(), Some(Ok(Register(Registers::R1))));
+        assert_eq!(tokenizer.next(), Some(Ok(Comma)));
+        assert_eq!(tokenizer.next(), Some(Ok(Register(Registers::R0))));
+        assert_eq!(tokenizer.next(), Some(Ok(Linebreak)));
+        assert_eq!(tokenizer.next(), Some(Ok(Semicolon)));
+        assert_eq!(tokenizer.next(), Some(Ok(Word("Comment".to_owned()))));
+        assert_eq!(tokenizer.next(), Some(Ok(Number(0b1010))));
+        assert_eq!(tokenizer.next(), Some(Ok(Linebreak)));
+        assert_eq!(tokenizer.next(), Some(Ok(Add)));
+        assert_eq!(tokenizer.next(), Some(Ok(Register(Registers::R0))));
+        assert_eq!(tokenizer.next(), Some(Ok(Comma)));
+        assert_eq!(tokenizer.next(), Some(Ok(Number(-10))));
+        assert_eq!(tokenizer.next(), None);
+    }
+
+    #[test]
+    fn test_span() {
+        let text = "loop:\n  BRnz loop";
+        let mut tokenizer = Tokenizer::with_file(text, 1);
+        tokenizer.next();
+        tokenizer.next();
+        tokenizer.next();
+        assert_eq!(tokenizer.next(), Some(Ok(Token::Br(true, true, false))));
+        let span = tokenizer.span();
+        assert_eq!((span.file, span.line, span.column), (1, 2, 3));
+        assert_eq!(tokenizer.next(), Some(Ok(Token::Word("loop".to_owned()))));
+        let span = tokenizer.span();
+        assert_eq!(
+            (span.line, span.column, span.start, span.end),
+            (2, 8, 13, 17)
+        );
+    }
+
+    #[test]
+    fn test_mnemonics() {
+        for mnemonic in super::MNEMONICS {
+            let token = Tokenizer::new(mnemonic).next();
+            assert!(
+                !matches!(token, Some(Ok(Token::Word(_)))),
+                "{mnemonic} is not a keyword"
+            );
+        }
+    }
+
+    #[test]
+    fn test_classic() {
+        use super::Token::*;
+        let text = "add r1, R2, #-5\nbrpn LOOP\nBrZnP x\n.FILL 0x1F X1F 0b101 -5 1_000 10";
+        let tokens: Vec<_> = Tokenizer::new(text)
+            .classic(true)
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(
+            tokens,
+            [
+                Add,
+                Register(Registers::R1),
+                Comma,
+                Register(Registers::R2),
+                Comma,
+                Number(-5),
+                Linebreak,
+                Br(true, false, true),
+                Word("LOOP".to_owned()),
+                Linebreak,
+                Br(true, true, true),
+                Word("x".to_owned()),
+                Linebreak,
+                Period,
+                Word("FILL".to_owned()),
+                Number(0x1f),
+                Number(0x1f),
+                Number(0b101),
+                Minus,
+                Number(5),
+                Number(1000),
+                Number(10),
+            ]
+        );
+
+        // without classic mode, all of these are labels
+        let text = "add r1 brpn 0x1F 1_000 10";
+        assert!(Tokenizer::new(text).all(|token| matches!(token, Ok(Word(_)))));
+    }
+
+    #[test]
+    fn test_characters() {
+        let text = "'A' '\\t' '\\x00' '\\''";
+        let tokens: Vec<_> = Tokenizer::new(text).map(Result::unwrap).collect();
+        assert_eq!(
+            tokens,
+            [
+                Token::Number(65),
+                Token::Number(9),
+                Token::Number(0),
+                Token::Number(39)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_peek() {
+        let text = "ADD\nloop";
+        let mut tokenizer = Tokenizer::new(text);
+        assert_eq!(tokenizer.next(), Some(Ok(Token::Add)));
+        assert_eq!(tokenizer.peek(), Some(&Ok(Token::Linebreak)));
+        assert_eq!(tokenizer.span().column, 1);
+        assert!(!tokenizer.at_line_start());
+        assert_eq!(tokenizer.next(), Some(Ok(Token::Linebreak)));
+        assert!(tokenizer.at_line_start());
+        assert_eq!(tokenizer.next(), Some(Ok(Token::Word("loop".to_owned()))));
+        assert_eq!(tokenizer.span().line, 2);
+        assert_eq!(tokenizer.peek(), None);
         assert_eq!(tokenizer.next(), None);
     }
 }
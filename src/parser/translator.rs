@@ -1,313 +1,697 @@
 use crate::lc_3::ops::{jsr, jsrr, store_word};
-use std::collections::HashMap;
+use std::{
+    collections::{hash_map::Entry, HashMap, HashSet},
+    fmt,
+};
 
 use crate::lc_3::{
     ops::{
         add_const, add_reg, and_const, and_reg, branch, jmp, lea, load_byte, load_word, lshf, not,
         ret, return_from_interrupt, rshfa, rshfl, store_byte, trap, xor_const, xor_reg,
     },
+    pseudo::{clr, dec, inc, ldi16, mov, neg, nop, pop, push, sub},
     registers::Registers,
 };
 
-use super::tokenizer::{Token, Tokenizer};
+use super::{
+    expr::{is_symbol, BinaryOp, Constant, EvalError, Expr, Scope, UnaryOp},
+    include::Loader,
+    macros::{is_expanded_local, Macro},
+    scanner::LexError,
+    source::{FileId, Span},
+    suggest::closest,
+    tokenizer::{Token, Tokenizer, HALT_VECTOR, MNEMONICS, TRAPS},
+    warning::{Warning, LARGE_GAP},
+};
 
 fn eat_comment(tokenizer: &mut Tokenizer) {
     for next in tokenizer {
-        if let Token::Linebreak = next {
+        if let Ok(Token::Linebreak) = next {
             return;
         }
     }
 }
 
+/// whether `name` is a local label like `@loop`, which belongs to the last label that is not local
+fn is_local(name: &str) -> bool {
+    name.len() > 1 && name.starts_with('@')
+}
+
+/// the name a label is known by, which includes the label `scope` for local labels,
+/// as in `print@loop` for `@loop` after `print:`
+fn scoped(scope: &str, label: String) -> String {
+    if is_local(&label) {
+        format!("{scope}{label}")
+    } else {
+        label
+    }
+}
+
+/// returns the next token, turning malformed input into an [`OperandError`]
+fn next_token(tokenizer: &mut Tokenizer) -> Result<Option<Token>, OperandError> {
+    tokenizer.next().transpose().map_err(OperandError::Lex)
+}
+
 macro_rules! comma {
-    ($tokenizer:ident) => {
-        let next = $tokenizer.next();
+    ($operands:ident) => {
+        let next = $operands.next_token()?;
         if !matches!(next, Some(Token::Comma)) {
-            return Err(());
+            return Err(OperandError::Syntax);
         }
     };
 }
 
 macro_rules! register {
-    ($tokenizer:ident) => {
-        if let Some(Token::Register(register)) = $tokenizer.next() {
+    ($operands:ident) => {
+        if let Some(Token::Register(register)) = $operands.next_token()? {
             register
         } else {
-            return Err(());
+            return Err(OperandError::Syntax);
         }
     };
 }
 
-fn negative_5bit(num: i32) -> u8 {
-    let tmp = (-num) as u32;
-    (((!tmp & 0b11111) + 1) & 0b11111).try_into().unwrap()
+/// an immediate operand field of an instruction or data directive
+#[derive(Debug, Clone, Copy)]
+struct Field {
+    name: &'static str,
+    bits: u32,
+    sign: Sign,
+}
+
+/// which values of a [`Field`] are valid
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Sign {
+    Unsigned,
+    Signed,
+    /// both, as in `.FILL #-1` and `.FILL xffff`
+    Either,
+}
+
+const IMM5: Field = Field {
+    name: "imm5",
+    bits: 5,
+    sign: Sign::Signed,
+};
+const AMOUNT4: Field = Field {
+    name: "amount4",
+    bits: 4,
+    sign: Sign::Unsigned,
+};
+const OFFSET6: Field = Field {
+    name: "offset6",
+    bits: 6,
+    sign: Sign::Signed,
+};
+const TRAPVECT8: Field = Field {
+    name: "trapvect8",
+    bits: 8,
+    sign: Sign::Unsigned,
+};
+const PCOFFSET9: Field = Field {
+    name: "PCoffset9",
+    bits: 9,
+    sign: Sign::Signed,
+};
+const PCOFFSET11: Field = Field {
+    name: "PCoffset11",
+    bits: 11,
+    sign: Sign::Signed,
+};
+const BYTE: Field = Field {
+    name: "byte",
+    bits: 8,
+    sign: Sign::Unsigned,
+};
+const WORD: Field = Field {
+    name: "word",
+    bits: 16,
+    sign: Sign::Unsigned,
+};
+const FILL: Field = Field {
+    name: "word",
+    bits: 16,
+    sign: Sign::Either,
+};
+
+impl Field {
+    fn min(&self) -> i32 {
+        match self.sign {
+            Sign::Unsigned => 0,
+            Sign::Signed | Sign::Either => -(1 << (self.bits - 1)),
+        }
+    }
+
+    fn max(&self) -> i32 {
+        match self.sign {
+            Sign::Signed => (1 << (self.bits - 1)) - 1,
+            Sign::Unsigned | Sign::Either => (1 << self.bits) - 1,
+        }
+    }
+
+    /// the bits of `value` this field holds, at the bottom of the word
+    fn encode(self, value: i32) -> u16 {
+        (value as u16) & (((1u32 << self.bits) - 1) as u16)
+    }
+
+    /// returns `value` if it can be encoded in this field
+    fn check(self, value: i32) -> Result<i32, OperandError> {
+        if (self.min()..=self.max()).contains(&value) {
+            Ok(value)
+        } else {
+            Err(OperandError::OutOfRange {
+                value,
+                field: self,
+                span: None,
+            })
+        }
+    }
+}
+
+/// why the operands of a statement could not be parsed
+#[derive(Debug)]
+enum OperandError {
+    Syntax,
+    Lex(LexError),
+    OutOfRange {
+        value: i32,
+        field: Field,
+        /// the whole operand, if it is longer than the last token read
+        span: Option<Span>,
+    },
+    /// an expression that has to be known at this point, but cannot be evaluated
+    Eval(EvalError),
+}
+
+/// an immediate operand whose value is not known yet,
+/// filled in once the whole translation unit is read, or by `link` if it depends on labels
+#[derive(Debug)]
+struct Fixup {
+    /// byte offset of the statement in its section, which `$` refers to
+    current_addr: i32,
+    section: usize,
+    /// index of the word to fill in within the section
+    index: usize,
+    /// position of the field within the word
+    shift: u32,
+    field: Field,
+    value: Expr,
+    span: Span,
+}
+
+/// the operands of a single statement
+struct Operands<'t, 'a> {
+    tokenizer: &'t mut Tokenizer<'a>,
+    constants: &'t HashMap<String, Constant>,
+    /// the labels defined so far, for operands that have to be known right away
+    labels: &'t HashMap<String, Label>,
+    /// the label local labels belong to
+    scope: &'t str,
+    origin: i32,
+    section: usize,
+    current_addr: i32,
+    /// fixups with `index` relative to the first word of the statement
+    fixups: Vec<Fixup>,
+    /// the symbols referred to so far, which tell the labels that are used
+    used: &'t mut HashSet<String>,
+}
+
+impl OperandError {
+    /// reports the error at `span`, the operand it is about
+    fn at(self, span: Span) -> Self {
+        match self {
+            OperandError::OutOfRange { value, field, .. } => OperandError::OutOfRange {
+                value,
+                field,
+                span: Some(span),
+            },
+            error => error,
+        }
+    }
+}
+
+impl<'t, 'a> Operands<'t, 'a> {
+    fn next_token(&mut self) -> Result<Option<Token>, OperandError> {
+        next_token(self.tokenizer)
+    }
+
+    /// the span from `start` up to the last token read
+    fn span_from(&self, start: Span) -> Span {
+        Span {
+            end: self.tokenizer.span().end,
+            ..start
+        }
+    }
+
+    /// parses an expression starting with `first`, the token already read
+    fn expr(&mut self, first: Option<Token>) -> Result<Expr, OperandError> {
+        let left = self.unary(first)?;
+        self.binary(left, 0)
+    }
+
+    /// parses the operators binding tighter than `min_precedence` following `left`
+    fn binary(&mut self, mut left: Expr, min_precedence: u8) -> Result<Expr, OperandError> {
+        while let Some(op) = self.peek_operator() {
+            if op.precedence() <= min_precedence {
+                break;
+            }
+            self.next_token()?;
+            let span = self.tokenizer.span();
+            let first = self.next_token()?;
+            let right = self.unary(first)?;
+            let right = self.binary(right, op.precedence())?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right), span);
+        }
+        Ok(left)
+    }
+
+    /// parses an operand of a binary operator, starting with `token`
+    fn unary(&mut self, token: Option<Token>) -> Result<Expr, OperandError> {
+        let span = self.tokenizer.span();
+        let op = match token {
+            Some(Token::Number(value)) => return Ok(Expr::Number(value)),
+            Some(Token::Word(name)) if is_symbol(&name) || is_local(&name) => {
+                let name = scoped(self.scope, self.members(name)?);
+                self.used.insert(name.clone());
+                return Ok(Expr::Symbol(name, span));
+            }
+            Some(Token::Dollar) => return Ok(Expr::Location(span)),
+            Some(Token::LeftParen) => {
+                let first = self.next_token()?;
+                let inner = self.expr(first)?;
+                if self.next_token()? != Some(Token::RightParen) {
+                    return Err(OperandError::Syntax);
+                }
+                return Ok(inner);
+            }
+            Some(Token::Minus) => UnaryOp::Negate,
+            Some(Token::Tilde) => UnaryOp::Not,
+            Some(Token::Plus) => {
+                let operand = self.next_token()?;
+                return self.unary(operand);
+            }
+            _ => return Err(OperandError::Syntax),
+        };
+        let operand = self.next_token()?;
+        Ok(Expr::Unary(op, Box::new(self.unary(operand)?)))
+    }
+
+    /// appends the fields following `name` like `.next` in `node.next`
+    fn members(&mut self, mut name: String) -> Result<String, OperandError> {
+        while let Some(Ok(Token::Period)) = self.tokenizer.peek() {
+            self.next_token()?;
+            match self.next_token()? {
+                Some(Token::Word(field)) if is_symbol(&field) => {
+                    name.push('.');
+                    name.push_str(&field);
+                }
+                _ => return Err(OperandError::Syntax),
+            }
+        }
+        Ok(name)
+    }
+
+    /// the binary operator following the current token, without consuming it
+    fn peek_operator(&mut self) -> Option<BinaryOp> {
+        Some(match self.tokenizer.peek()? {
+            Ok(Token::Plus) => BinaryOp::Add,
+            Ok(Token::Minus) => BinaryOp::Subtract,
+            Ok(Token::Star) => BinaryOp::Multiply,
+            Ok(Token::Slash) => BinaryOp::Divide,
+            Ok(Token::Percent) => BinaryOp::Remainder,
+            Ok(Token::ShiftLeft) => BinaryOp::ShiftLeft,
+            Ok(Token::ShiftRight) => BinaryOp::ShiftRight,
+            Ok(Token::Ampersand) => BinaryOp::And,
+            Ok(Token::Caret) => BinaryOp::Xor,
+            Ok(Token::Pipe) => BinaryOp::Or,
+            _ => return None,
+        })
+    }
+
+    /// parses the immediate `token` for `field` of the first word of the statement
+    fn immediate(&mut self, token: Option<Token>, field: Field) -> Result<i32, OperandError> {
+        self.immediate_at(token, field, 0, 0)
+    }
+
+    /// parses the immediate `token` for `field` at bit `shift` of word `index` of the statement.
+    /// constants which are not defined yet are filled in later and read as 0 for now
+    fn immediate_at(
+        &mut self,
+        token: Option<Token>,
+        field: Field,
+        index: usize,
+        shift: u32,
+    ) -> Result<i32, OperandError> {
+        let start = self.tokenizer.span();
+        let value = self.expr(token)?;
+        let span = self.span_from(start);
+        // labels and `$` move when translation units are put together
+        let scope = Scope {
+            constants: self.constants,
+            label: &|_| None,
+            location: None,
+        };
+        match value.evaluate(&scope) {
+            Ok(value) => field.check(value).map_err(|error| error.at(span)),
+            Err(
+                EvalError::Undefined(_, _)
+                | EvalError::Recursive(_, _)
+                | EvalError::UnknownLocation(_),
+            ) => {
+                self.fixups.push(Fixup {
+                    current_addr: self.current_addr,
+                    section: self.section,
+                    index,
+                    shift,
+                    field,
+                    value,
+                    span,
+                });
+                Ok(0)
+            }
+            Err(error) => Err(OperandError::Eval(error)),
+        }
+    }
+
+    /// parses an immediate that has to be known right away, as it moves the location
+    fn defined(&mut self, token: Option<Token>, field: Field) -> Result<i32, OperandError> {
+        // only the text section is known to start at the origin
+        let (labels, origin) = (self.labels, self.origin);
+        let scope = Scope {
+            constants: self.constants,
+            label: &|name| {
+                let label = labels.get(name).filter(|label| label.section == 0)?;
+                Some(origin + label.addr)
+            },
+            location: (self.section == 0).then_some(origin + self.current_addr),
+        };
+        let start = self.tokenizer.span();
+        let value = self.expr(token)?;
+        let span = self.span_from(start);
+        let value = value.evaluate(&scope).map_err(OperandError::Eval)?;
+        field.check(value).map_err(|error| error.at(span))
+    }
 }
 
-fn parse_add(tokenizer: &mut Tokenizer) -> Result<u16, ()> {
-    let dest = register!(tokenizer);
+fn parse_add(operands: &mut Operands) -> Result<u16, OperandError> {
+    let dest = register!(operands);
 
-    comma!(tokenizer);
+    comma!(operands);
 
-    let source = register!(tokenizer);
+    let source = register!(operands);
 
-    comma!(tokenizer);
+    comma!(operands);
 
-    let operand = tokenizer.next();
-    if let Some(Token::Number(num)) = operand {
-        let num = negative_5bit(num);
-        Ok(add_const(dest, source, num))
-    } else if let Some(Token::Register(reg)) = operand {
+    let operand = operands.next_token()?;
+    if let Some(Token::Register(reg)) = operand {
         Ok(add_reg(dest, source, reg))
     } else {
-        Err(())
+        let imm5 = operands.immediate(operand, IMM5)?;
+        Ok(add_const(dest, source, IMM5.encode(imm5) as u8))
     }
 }
 
-fn parse_and(tokenizer: &mut Tokenizer) -> Result<u16, ()> {
-    let dest = register!(tokenizer);
+fn parse_and(operands: &mut Operands) -> Result<u16, OperandError> {
+    let dest = register!(operands);
 
-    comma!(tokenizer);
+    comma!(operands);
 
-    let source = register!(tokenizer);
+    let source = register!(operands);
 
-    comma!(tokenizer);
+    comma!(operands);
 
-    let operand = tokenizer.next();
-    if let Some(Token::Number(num)) = operand {
-        Ok(and_const(dest, source, negative_5bit(num)))
-    } else if let Some(Token::Register(reg)) = operand {
+    let operand = operands.next_token()?;
+    if let Some(Token::Register(reg)) = operand {
         Ok(and_reg(dest, source, reg))
     } else {
-        Err(())
+        let imm5 = operands.immediate(operand, IMM5)?;
+        Ok(and_const(dest, source, IMM5.encode(imm5) as u8))
     }
 }
 
-fn parse_xor(tokenizer: &mut Tokenizer) -> Result<u16, ()> {
-    let dest = register!(tokenizer);
+fn parse_xor(operands: &mut Operands) -> Result<u16, OperandError> {
+    let dest = register!(operands);
 
-    comma!(tokenizer);
+    comma!(operands);
 
-    let source = register!(tokenizer);
+    let source = register!(operands);
 
-    comma!(tokenizer);
+    comma!(operands);
 
-    let operand = tokenizer.next();
-    if let Some(Token::Number(num)) = operand {
-        Ok(xor_const(dest, source, negative_5bit(num)))
-    } else if let Some(Token::Register(reg)) = operand {
+    let operand = operands.next_token()?;
+    if let Some(Token::Register(reg)) = operand {
         Ok(xor_reg(dest, source, reg))
     } else {
-        Err(())
+        let imm5 = operands.immediate(operand, IMM5)?;
+        Ok(xor_const(dest, source, IMM5.encode(imm5) as u8))
     }
 }
 
-fn parse_not(tokenizer: &mut Tokenizer) -> Result<u16, ()> {
-    let dest = register!(tokenizer);
+fn parse_not(operands: &mut Operands) -> Result<u16, OperandError> {
+    let dest = register!(operands);
 
-    comma!(tokenizer);
+    comma!(operands);
 
-    let source = register!(tokenizer);
+    let source = register!(operands);
 
     Ok(not(dest, source))
 }
 
-fn parse_jmp(tokenizer: &mut Tokenizer) -> Result<u16, ()> {
-    if let Some(Token::Register(base_register)) = tokenizer.next() {
+fn parse_jmp(operands: &mut Operands) -> Result<u16, OperandError> {
+    if let Some(Token::Register(base_register)) = operands.next_token()? {
         Ok(jmp(base_register))
     } else {
-        Err(())
+        Err(OperandError::Syntax)
     }
 }
 
-fn parse_br(tokenizer: &mut Tokenizer) -> Result<String, ()> {
-    if let Some(Token::Word(label)) = tokenizer.next() {
-        Ok(label)
-    } else {
-        Err(())
+/// the operand of BR, LEA and JSR
+enum Target {
+    /// the address to reach, like `loop + #2` or a constant, and its span
+    Address(Expr, Span),
+    /// a PC offset given as a number, like `BRnzp #-1`
+    Offset(i32),
+}
+
+/// parses the address to reach, or a numeric PC offset fitting into `field`
+fn parse_target(operands: &mut Operands, field: Field) -> Result<Target, OperandError> {
+    let first = operands.next_token()?;
+    let start = operands.tokenizer.span();
+    let target = operands.expr(first)?;
+    let span = operands.span_from(start);
+    match target {
+        Expr::Number(offset) => Ok(Target::Offset(
+            field.check(offset).map_err(|error| error.at(span))?,
+        )),
+        target => Ok(Target::Address(target, span)),
     }
 }
 
-fn parse_lea(tokenizer: &mut Tokenizer) -> Result<(Registers, String), ()> {
-    let reg = register!(tokenizer);
+fn parse_br(operands: &mut Operands) -> Result<Target, OperandError> {
+    parse_target(operands, PCOFFSET9)
+}
 
-    comma!(tokenizer);
+fn parse_lea(operands: &mut Operands) -> Result<(Registers, Target), OperandError> {
+    let reg = register!(operands);
 
-    if let Some(Token::Word(label)) = tokenizer.next() {
-        Ok((reg, label))
-    } else {
-        Err(())
-    }
+    comma!(operands);
+
+    Ok((reg, parse_target(operands, PCOFFSET9)?))
 }
 
-fn parse_shift(tokenizer: &mut Tokenizer) -> Result<(Registers, Registers, u8), ()> {
-    let dest = register!(tokenizer);
+fn parse_shift(operands: &mut Operands) -> Result<(Registers, Registers, u8), OperandError> {
+    let dest = register!(operands);
 
-    comma!(tokenizer);
+    comma!(operands);
 
-    let source = register!(tokenizer);
+    let source = register!(operands);
 
-    comma!(tokenizer);
+    comma!(operands);
 
-    let amount = if let Some(Token::Number(amount)) = tokenizer.next() {
-        amount
-    } else {
-        return Err(());
-    };
+    let amount = operands.next_token()?;
+    let amount = operands.immediate(amount, AMOUNT4)?;
 
-    Ok((dest, source, (amount & 0b1111) as u8))
+    Ok((dest, source, amount as u8))
 }
 
-fn parse_trap(tokenizer: &mut Tokenizer) -> Result<u16, ()> {
-    if let Some(Token::Number(vect)) = tokenizer.next() {
-        Ok(trap(vect.try_into().unwrap()))
-    } else {
-        Err(())
-    }
+fn parse_trap(operands: &mut Operands) -> Result<u16, OperandError> {
+    let vect = operands.next_token()?;
+    Ok(trap(operands.immediate(vect, TRAPVECT8)? as u8))
 }
 
-fn parse_stb(tokenizer: &mut Tokenizer) -> Result<u16, ()> {
-    let source = register!(tokenizer);
+/// parses `.TRAPDEF name, vector`, where the vector has to be known already
+fn parse_trapdef(operands: &mut Operands) -> Result<(String, u8), OperandError> {
+    let name = match operands.next_token()? {
+        Some(Token::Word(name)) if is_symbol(&name) => name,
+        _ => return Err(OperandError::Syntax),
+    };
+    comma!(operands);
+    let vect = operands.next_token()?;
+    Ok((name, operands.defined(vect, TRAPVECT8)? as u8))
+}
 
-    comma!(tokenizer);
+fn parse_stb(operands: &mut Operands) -> Result<u16, OperandError> {
+    let source = register!(operands);
 
-    let base = register!(tokenizer);
+    comma!(operands);
 
-    comma!(tokenizer);
+    let base = register!(operands);
 
-    let offset = if let Some(Token::Number(offset)) = tokenizer.next() {
-        offset
-    } else {
-        return Err(());
-    };
+    comma!(operands);
+
+    let offset = operands.next_token()?;
+    let offset = operands.immediate(offset, OFFSET6)?;
 
     Ok(store_byte(source, base, offset))
 }
 
-fn parse_stw(tokenizer: &mut Tokenizer) -> Result<u16, ()> {
-    let source = register!(tokenizer);
+fn parse_stw(operands: &mut Operands) -> Result<u16, OperandError> {
+    let source = register!(operands);
 
-    comma!(tokenizer);
+    comma!(operands);
 
-    let base = register!(tokenizer);
+    let base = register!(operands);
 
-    comma!(tokenizer);
+    comma!(operands);
 
-    let offset = if let Some(Token::Number(offset)) = tokenizer.next() {
-        offset
-    } else {
-        return Err(());
-    };
+    let offset = operands.next_token()?;
+    let offset = operands.immediate(offset, OFFSET6)?;
 
     Ok(store_word(source, base, offset))
 }
 
-fn parse_ldb(tokenizer: &mut Tokenizer) -> Result<u16, ()> {
-    let dest = register!(tokenizer);
+fn parse_ldb(operands: &mut Operands) -> Result<u16, OperandError> {
+    let dest = register!(operands);
 
-    comma!(tokenizer);
+    comma!(operands);
 
-    let base = register!(tokenizer);
+    let base = register!(operands);
 
-    comma!(tokenizer);
+    comma!(operands);
 
-    let offset = if let Some(Token::Number(offset)) = tokenizer.next() {
-        offset
-    } else {
-        return Err(());
-    };
+    let offset = operands.next_token()?;
+    let offset = operands.immediate(offset, OFFSET6)?;
 
     Ok(load_byte(dest, base, offset))
 }
 
-fn parse_ldw(tokenizer: &mut Tokenizer) -> Result<u16, ()> {
-    let dest = register!(tokenizer);
+fn parse_ldw(operands: &mut Operands) -> Result<u16, OperandError> {
+    let dest = register!(operands);
 
-    comma!(tokenizer);
+    comma!(operands);
 
-    let base = register!(tokenizer);
+    let base = register!(operands);
 
-    comma!(tokenizer);
+    comma!(operands);
 
-    let offset = if let Some(Token::Number(offset)) = tokenizer.next() {
-        offset
-    } else {
-        return Err(());
-    };
+    let offset = operands.next_token()?;
+    let offset = operands.immediate(offset, OFFSET6)?;
 
     Ok(load_word(dest, base, offset))
 }
 
-fn parse_jsrr(tokenizer: &mut Tokenizer) -> Result<u16, ()> {
-    if let Some(Token::Register(base)) = tokenizer.next() {
+fn parse_jsrr(operands: &mut Operands) -> Result<u16, OperandError> {
+    if let Some(Token::Register(base)) = operands.next_token()? {
         Ok(jsrr(base))
     } else {
-        Err(())
+        Err(OperandError::Syntax)
     }
 }
 
-fn parse_jsr(tokenizer: &mut Tokenizer) -> Result<String, ()> {
-    if let Some(Token::Word(label)) = tokenizer.next() {
-        Ok(label)
-    } else {
-        Err(())
-    }
+fn parse_jsr(operands: &mut Operands) -> Result<Target, OperandError> {
+    parse_target(operands, PCOFFSET11)
+}
+
+fn parse_mov(operands: &mut Operands) -> Result<u16, OperandError> {
+    let dest = register!(operands);
+
+    comma!(operands);
+
+    let source = register!(operands);
+
+    Ok(mov(dest, source))
+}
+
+fn parse_neg(operands: &mut Operands) -> Result<Vec<u16>, OperandError> {
+    let dest = register!(operands);
+
+    comma!(operands);
+
+    let source = register!(operands);
+
+    Ok(neg(dest, source))
+}
+
+fn parse_sub(operands: &mut Operands) -> Result<Vec<u16>, OperandError> {
+    let dest = register!(operands);
+
+    comma!(operands);
+
+    let source1 = register!(operands);
+
+    comma!(operands);
+
+    let source2 = register!(operands);
+
+    Ok(sub(dest, source1, source2))
+}
+
+/// parses the single register operand of `CLR`, `INC`, `DEC`, `PUSH` and `POP`
+fn parse_register(operands: &mut Operands) -> Result<Registers, OperandError> {
+    Ok(register!(operands))
+}
+
+/// parses the operands of `LDI16`, whose value has to be known here
+/// as the number of words it takes depends on it
+fn parse_ldi16(operands: &mut Operands) -> Result<Vec<u16>, OperandError> {
+    let dest = register!(operands);
+
+    comma!(operands);
+
+    let value = operands.next_token()?;
+    Ok(ldi16(dest, FILL.encode(operands.defined(value, FILL)?)))
 }
 
-fn parse_define_bytes(tokenizer: &mut Tokenizer) -> Result<Vec<u16>, ()> {
+/// parses the bytes of `DB`, or of `DBZ` if `terminated`, which appends a zero byte
+fn parse_define_bytes(operands: &mut Operands, terminated: bool) -> Result<Vec<u8>, OperandError> {
     let mut bytes: Vec<u8> = vec![];
+    // the first byte goes into the upper half of the last word at an odd address
+    let skip = operands.current_addr as usize % 2;
 
-    while let Some(next) = tokenizer.next() {
-        if let Token::Number(num) = next {
-            if (0..0x100).contains(&num) {
-                bytes.push(num.try_into().unwrap())
-            } else {
-                // todo report
-                return Err(());
-            }
-        } else if let Token::Str(string) = next {
-            for ch in string.bytes() {
-                bytes.push(ch);
-            }
+    while let Some(next) = operands.next_token()? {
+        if let Token::Str(string) = next {
+            // every char of a string stands for one byte of the input
+            bytes.extend(string.chars().map(|ch| ch as u8));
         } else {
-            return Err(());
+            let position = skip + bytes.len();
+            let (index, shift) = (position / 2, position as u32 % 2 * 8);
+            bytes.push(operands.immediate_at(Some(next), BYTE, index, shift)? as u8);
         }
 
-        if let Some(next) = tokenizer.next() {
+        if let Some(next) = operands.next_token()? {
             match next {
                 Token::Comma => {}
                 Token::Linebreak => break,
-                _ => return Err(()),
+                _ => return Err(OperandError::Syntax),
             }
         }
     }
-
-    let mut bytes = bytes.iter();
-    let mut words = vec![];
-    while let Some(&lower) = bytes.next() {
-        let &upper = bytes.next().unwrap_or(&0);
-        words.push((upper as u16) << 8 | (lower as u16));
+    if terminated {
+        bytes.push(0);
     }
-    Ok(words)
+    Ok(bytes)
 }
-fn parse_define_words(tokenizer: &mut Tokenizer) -> Result<Vec<u16>, ()> {
+fn parse_define_words(operands: &mut Operands) -> Result<Vec<u16>, OperandError> {
     let mut words: Vec<u16> = vec![];
-    while let Some(next) = tokenizer.next() {
-        if let Token::Number(num) = next {
-            if (0..0x10000).contains(&num) {
-                words.push(num.try_into().unwrap())
-            } else {
-                // todo report
-                return Err(());
-            }
-        } else {
-            return Err(());
-        }
+    while let Some(next) = operands.next_token()? {
+        let index = words.len();
+        words.push(operands.immediate_at(Some(next), WORD, index, 0)? as u16);
 
-        if let Some(next) = tokenizer.next() {
+        if let Some(next) = operands.next_token()? {
             match next {
                 Token::Comma => {}
                 Token::Linebreak => break,
-                _ => return Err(()),
+                _ => return Err(OperandError::Syntax),
             }
         }
     }
@@ -315,58 +699,251 @@ fn parse_define_words(tokenizer: &mut Tokenizer) -> Result<Vec<u16>, ()> {
     Ok(words)
 }
 
-fn parse_set_loc(tokenizer: &mut Tokenizer) -> Result<i32, ()> {
-    if !matches!(tokenizer.next(), Some(Token::Equals)) {
-        return Err(());
+/// parses the byte address of `. = addr` and `.ORIG addr`
+fn parse_address(operands: &mut Operands) -> Result<i32, OperandError> {
+    let addr = operands.next_token()?;
+    operands.defined(addr, WORD)
+}
+
+/// parses the value of `.FILL`, which may be given signed or unsigned
+fn parse_fill(operands: &mut Operands) -> Result<u16, OperandError> {
+    let value = operands.next_token()?;
+    Ok(FILL.encode(operands.immediate(value, FILL)?))
+}
+
+/// parses the number of words `.BLKW` reserves
+fn parse_blkw(operands: &mut Operands) -> Result<usize, OperandError> {
+    let count = operands.next_token()?;
+    Ok(operands.defined(count, WORD)? as usize)
+}
+
+/// parses the alignment of `.ALIGN`, treating 0 like 1
+fn parse_align(operands: &mut Operands) -> Result<i32, OperandError> {
+    let alignment = operands.next_token()?;
+    Ok(operands.defined(alignment, WORD)?.max(1))
+}
+
+/// parses the string of `.STRINGZ`, which is stored with one character per word
+/// and terminated by a zero word
+fn parse_stringz(operands: &mut Operands) -> Result<Vec<u16>, OperandError> {
+    match operands.next_token()? {
+        Some(Token::Str(string)) => Ok(string
+            .chars()
+            .map(|ch| ch as u16)
+            .chain(std::iter::once(0))
+            .collect()),
+        _ => Err(OperandError::Syntax),
     }
-    if let Some(Token::Number(addr)) = tokenizer.next() {
-        // shift bc u16
-        Ok(addr >> 1)
-    } else {
-        Err(())
+}
+
+/// parses the name and value of `.EQU NAME, value`, where the comma is optional
+fn parse_equ(operands: &mut Operands) -> Result<(String, Expr), OperandError> {
+    let name = match operands.next_token()? {
+        Some(Token::Word(name)) if is_symbol(&name) => name,
+        _ => return Err(OperandError::Syntax),
+    };
+    let mut value = operands.next_token()?;
+    if value == Some(Token::Comma) {
+        value = operands.next_token()?;
     }
+    Ok((name, operands.expr(value)?))
 }
 
-macro_rules! parse {
-    ($func_name:ident, $display_name:expr, $line_number:expr, $tokenizer:ident, $instructions:ident, $address:ident) => {{
-        if let Ok(instr) = $func_name(&mut $tokenizer) {
-            $instructions.push(instr);
-            $address += 1;
-        } else {
-            return Err(ParseError::StatementSyntaxError(
-                $display_name.to_owned(),
-                $line_number,
-            ));
+/// parses the name and parameters of `.MACRO name param, ...`
+fn parse_macro(operands: &mut Operands) -> Result<(String, Vec<String>), OperandError> {
+    let Some(Token::Word(name)) = operands.next_token()? else {
+        return Err(OperandError::Syntax);
+    };
+    let mut params = vec![];
+    loop {
+        match operands.next_token()? {
+            None | Some(Token::Linebreak) => break,
+            Some(Token::Semicolon) => {
+                eat_comment(operands.tokenizer);
+                break;
+            }
+            Some(Token::Word(param)) if is_symbol(&param) => params.push(param),
+            _ => return Err(OperandError::Syntax),
+        }
+        match operands.next_token()? {
+            None | Some(Token::Linebreak) => break,
+            Some(Token::Semicolon) => {
+                eat_comment(operands.tokenizer);
+                break;
+            }
+            Some(Token::Comma) => {}
+            _ => return Err(OperandError::Syntax),
+        }
+    }
+    Ok((name, params))
+}
+
+/// parses the arguments of a macro, separated by commas outside of parentheses
+fn parse_macro_args(operands: &mut Operands) -> Result<Vec<Vec<Token>>, OperandError> {
+    let mut args = vec![];
+    let mut arg = vec![];
+    let mut depth = 0;
+    loop {
+        match operands.next_token()? {
+            None | Some(Token::Linebreak) => break,
+            Some(Token::Semicolon) => {
+                eat_comment(operands.tokenizer);
+                break;
+            }
+            Some(Token::Comma) if depth == 0 => args.push(std::mem::take(&mut arg)),
+            Some(token) => {
+                match token {
+                    Token::LeftParen => depth += 1,
+                    Token::RightParen => depth -= 1,
+                    _ => {}
+                }
+                arg.push(token);
+            }
+        }
+    }
+    if !args.is_empty() || !arg.is_empty() {
+        args.push(arg);
+    }
+    if args.iter().any(Vec::is_empty) {
+        return Err(OperandError::Syntax);
+    }
+    Ok(args)
+}
+
+/// parses the end of a line, which may hold a comment
+fn parse_line_end(operands: &mut Operands) -> Result<(), OperandError> {
+    match operands.next_token()? {
+        None | Some(Token::Linebreak) => Ok(()),
+        Some(Token::Semicolon) => {
+            eat_comment(operands.tokenizer);
+            Ok(())
+        }
+        _ => Err(OperandError::Syntax),
+    }
+}
+
+/// parses the condition of `.IF expr`, which holds if it is not zero
+fn parse_if(operands: &mut Operands) -> Result<bool, OperandError> {
+    let value = operands.next_token()?;
+    let value = operands.defined(value, WORD)?;
+    parse_line_end(operands)?;
+    Ok(value != 0)
+}
+
+/// parses the name of `.IFDEF NAME` and `.IFNDEF NAME`
+fn parse_ifdef(operands: &mut Operands) -> Result<String, OperandError> {
+    let Some(Token::Word(name)) = operands.next_token()? else {
+        return Err(OperandError::Syntax);
+    };
+    parse_line_end(operands)?;
+    Ok(name)
+}
+
+/// parses the name of `.STRUCT name`
+fn parse_struct(operands: &mut Operands) -> Result<String, OperandError> {
+    let name = match operands.next_token()? {
+        Some(Token::Word(name)) if is_symbol(&name) => name,
+        _ => return Err(OperandError::Syntax),
+    };
+    parse_line_end(operands)?;
+    Ok(name)
+}
+
+/// parses a field of `.STRUCT` after its name, like `: DW` or `: DB #8` for an array,
+/// returning whether it holds words and its number of elements
+fn parse_field(operands: &mut Operands) -> Result<(bool, i32), OperandError> {
+    if operands.next_token()? != Some(Token::Colon) {
+        return Err(OperandError::Syntax);
+    }
+    let words = match operands.next_token()? {
+        Some(Token::DefineBytes) => false,
+        Some(Token::DefineWords) => true,
+        _ => return Err(OperandError::Syntax),
+    };
+    let count = match operands.next_token()? {
+        None | Some(Token::Linebreak) => return Ok((words, 1)),
+        Some(Token::Semicolon) => {
+            eat_comment(operands.tokenizer);
+            return Ok((words, 1));
         }
+        count => operands.defined(count, WORD)?,
+    };
+    parse_line_end(operands)?;
+    Ok((words, count))
+}
+
+/// parses the path of `.INCLUDE "path"`
+fn parse_include(operands: &mut Operands) -> Result<(String, Span), OperandError> {
+    let Some(Token::Str(path)) = operands.next_token()? else {
+        return Err(OperandError::Syntax);
+    };
+    let span = operands.tokenizer.span();
+    parse_line_end(operands)?;
+    Ok((path, span))
+}
+
+/// the directives following a `.`, besides `. = addr`
+const DIRECTIVES: [&str; 21] = [
+    ".ORIG", ".FILL", ".BLKW", ".STRINGZ", ".ALIGN", ".END", ".EQU", ".MACRO", ".ENDM", ".IF",
+    ".IFDEF", ".IFNDEF", ".ELSE", ".ENDIF", ".INCLUDE", ".TEXT", ".DATA", ".BSS", ".TRAPDEF",
+    ".STRUCT", ".ENDS",
+];
+
+macro_rules! parse {
+    ($self:ident, $func_name:ident, $display_name:expr) => {{
+        let instr = $self.operands($display_name, $func_name)?;
+        $self.instructions.push(instr);
+        $self.current_addr += 2;
+    }};
+}
+
+/// like `parse!`, for statements emitting any number of words
+macro_rules! parse_words {
+    ($self:ident, $func_name:ident, $display_name:expr) => {{
+        let words = $self.operands($display_name, $func_name)?;
+        $self.current_addr += 2 * words.len() as i32;
+        $self.instructions.extend(words);
     }};
 }
 
 #[derive(Debug)]
 struct Branch {
     current_addr: i32,
+    section: usize,
     n: bool,
     z: bool,
     p: bool,
     index: usize,
+    /// the address to reach, in which constants are substituted at the end of the translation
+    target: Expr,
+    /// the target as written, which errors refer to
     label: String,
-    line_number: u32,
+    span: Span,
 }
 
 #[derive(Debug)]
 struct LoadEffectiveAddress {
     current_addr: i32,
+    section: usize,
     index: usize,
     dest: Registers,
+    /// the address to reach, in which constants are substituted at the end of the translation
+    target: Expr,
+    /// the target as written, which errors refer to
     label: String,
-    line_number: u32,
+    span: Span,
 }
 
 #[derive(Debug)]
 struct JumpSubroutine {
     current_addr: i32,
+    section: usize,
     index: usize,
+    /// the address to reach, in which constants are substituted at the end of the translation
+    target: Expr,
+    /// the target as written, which errors refer to
     label: String,
-    line_number: u32,
+    span: Span,
 }
 
 const PLACEHOLDER: u16 = 0xaaaa;
@@ -376,399 +953,940 @@ enum AddressResolving {
     Branch(Branch),
     Lea(LoadEffectiveAddress),
     Jsr(JumpSubroutine),
+    /// an immediate operand depending on labels or `$`,
+    /// like the absolute addresses of a jump table in `DW handler_a, handler_b`
+    Expression(Fixup),
 }
 
 #[derive(Debug, PartialEq)]
 pub enum ParseError {
-    StatementSyntaxError(String, u32),
-    LabelSyntaxError(String, u32),
-    UnexpectedToken(String, u32),
-    InvalidLocation(u32),
-}
-
-#[derive(Debug, PartialEq)]
-pub enum LinkError {
-    LabelNotResolvedError(String, u32),
-}
-
-#[derive(Debug)]
-pub struct TranslationOutput {
-    instructions: Vec<u16>,
-    labels: HashMap<String, i32>,
-    to_resolve: Vec<AddressResolving>,
-    last_address: i32,
+    StatementSyntaxError(String, Span),
+    LabelSyntaxError(String, Span),
+    /// a word followed by operands that is not a known instruction
+    UnknownMnemonic {
+        mnemonic: String,
+        /// the known mnemonic closest to `mnemonic`
+        suggestion: Option<String>,
+        span: Span,
+    },
+    UnexpectedToken(String, Span),
+    InvalidLocation(Span),
+    LexError(LexError, Span),
+    DuplicateLabel {
+        label: String,
+        first: Span,
+        second: Span,
+    },
+    ImmediateOutOfRange {
+        value: i32,
+        min: i32,
+        max: i32,
+        field: &'static str,
+        span: Span,
+    },
+    DuplicateConstant {
+        name: String,
+        first: Span,
+        second: Span,
+    },
+    /// a constant whose value depends on itself
+    RecursiveConstant(String, Span),
+    /// a symbol in an operand that moves the location, which has to be defined earlier
+    ConstantUsedBeforeDefinition(String, Span),
+    /// an expression that cannot be evaluated, like a division by zero
+    InvalidExpression(EvalError),
+    DuplicateMacro {
+        name: String,
+        first: Span,
+        second: Span,
+    },
+    /// a `.MACRO` without `.ENDM`
+    UnterminatedMacro(String, Span),
+    /// a `.STRUCT` without `.ENDS`
+    UnterminatedStruct(String, Span),
+    /// a word field of a `.STRUCT` at an odd offset
+    MisalignedField(String, Span),
+    MacroArguments {
+        name: String,
+        expected: usize,
+        found: usize,
+        span: Span,
+    },
+    /// a macro used in its own expansion
+    RecursiveMacro(String, Span),
+    /// an `.ELSE` or `.ENDIF` without `.IF`, or a second `.ELSE`
+    UnmatchedConditional(String, Span),
+    /// an `.IF` without `.ENDIF`
+    UnterminatedConditional(Span),
+    /// an `.INCLUDE` of a file that cannot be found
+    IncludeNotFound(String, Span),
+    /// an `.INCLUDE` of a file that is being included already
+    RecursiveInclude(String, Span),
+    /// an instruction or word after an odd number of bytes
+    OddAddress(Span),
+    /// an instruction or data in `.BSS`, which only reserves space
+    DataInBss(Span),
+    /// `.ALIGN` to more than a word in `.DATA` or `.BSS`, whose addresses are only known to `link`
+    AlignmentOutsideText(i32, Span),
+    /// a `.TRAPDEF` giving a named trap another vector
+    TrapRedefinition {
+        name: String,
+        /// the vector it has already
+        vector: u8,
+        span: Span,
+    },
+    /// an error in the expansion of the macro `name`
+    InMacro {
+        error: Box<ParseError>,
+        name: String,
+        /// where the macro is used
+        call: Span,
+    },
 }
 
-impl TranslationOutput {
-    pub fn extend(&mut self, other: TranslationOutput) {
-        let offset_index = self.instructions.len();
-        // TODO make this more efficient maybe
-        // if it turns out to be slow
-        let mut labels = HashMap::new();
-        for (key, &value) in &other.labels {
-            labels.insert(key.to_owned(), value + self.last_address);
+impl ParseError {
+    /// position of the token that caused the error
+    pub fn span(&self) -> Span {
+        match self {
+            ParseError::StatementSyntaxError(_, span)
+            | ParseError::LabelSyntaxError(_, span)
+            | ParseError::UnknownMnemonic { span, .. }
+            | ParseError::UnexpectedToken(_, span)
+            | ParseError::InvalidLocation(span)
+            | ParseError::LexError(_, span)
+            | ParseError::DuplicateLabel { second: span, .. }
+            | ParseError::ImmediateOutOfRange { span, .. }
+            | ParseError::DuplicateConstant { second: span, .. }
+            | ParseError::RecursiveConstant(_, span)
+            | ParseError::ConstantUsedBeforeDefinition(_, span)
+            | ParseError::DuplicateMacro { second: span, .. }
+            | ParseError::UnterminatedMacro(_, span)
+            | ParseError::UnterminatedStruct(_, span)
+            | ParseError::MisalignedField(_, span)
+            | ParseError::MacroArguments { span, .. }
+            | ParseError::RecursiveMacro(_, span)
+            | ParseError::UnmatchedConditional(_, span)
+            | ParseError::UnterminatedConditional(span)
+            | ParseError::IncludeNotFound(_, span)
+            | ParseError::RecursiveInclude(_, span)
+            | ParseError::OddAddress(span)
+            | ParseError::DataInBss(span)
+            | ParseError::AlignmentOutsideText(_, span)
+            | ParseError::TrapRedefinition { span, .. } => *span,
+            ParseError::InvalidExpression(error) => error.span(),
+            ParseError::InMacro { error, .. } => error.span(),
         }
-        self.labels.extend(labels);
+    }
 
-        let mut to_resolve = Vec::with_capacity(other.to_resolve.len());
-        for res in other.to_resolve {
-            to_resolve.push(match res {
-                AddressResolving::Branch(mut branch) => {
-                    branch.current_addr += self.last_address;
-                    branch.index += offset_index;
-                    AddressResolving::Branch(branch)
-                }
-                AddressResolving::Lea(mut lea) => {
-                    lea.current_addr += self.last_address;
-                    lea.index += offset_index;
-                    AddressResolving::Lea(lea)
-                }
-                AddressResolving::Jsr(mut jsr) => {
-                    jsr.current_addr += self.last_address;
-                    jsr.index += offset_index;
-                    AddressResolving::Jsr(jsr)
-                }
-            });
+    /// stable identifier of the kind of error, shared with [`LinkError::code`]
+    pub fn code(&self) -> &'static str {
+        match self {
+            ParseError::StatementSyntaxError(_, _) => "E0001",
+            ParseError::LabelSyntaxError(_, _) => "E0002",
+            ParseError::UnexpectedToken(_, _) => "E0003",
+            ParseError::InvalidLocation(_) => "E0005",
+            ParseError::ImmediateOutOfRange { .. } => "E0006",
+            ParseError::LexError(_, _) => "E0008",
+            ParseError::DuplicateLabel { .. }
+            | ParseError::DuplicateConstant { .. }
+            | ParseError::DuplicateMacro { .. } => "E0009",
+            ParseError::UnknownMnemonic { .. } => "E0010",
+            ParseError::RecursiveConstant(_, _) => "E0012",
+            ParseError::ConstantUsedBeforeDefinition(_, _) => "E0013",
+            ParseError::InvalidExpression(_) => "E0014",
+            ParseError::UnterminatedMacro(_, _) | ParseError::UnterminatedStruct(_, _) => "E0015",
+            ParseError::MacroArguments { .. } => "E0016",
+            ParseError::RecursiveMacro(_, _) => "E0017",
+            ParseError::UnmatchedConditional(_, _) => "E0018",
+            ParseError::UnterminatedConditional(_) => "E0019",
+            ParseError::IncludeNotFound(_, _) => "E0021",
+            ParseError::RecursiveInclude(_, _) => "E0022",
+            ParseError::OddAddress(_) => "E0023",
+            ParseError::DataInBss(_) => "E0025",
+            ParseError::AlignmentOutsideText(_, _) => "E0029",
+            ParseError::TrapRedefinition { .. } => "E0027",
+            ParseError::MisalignedField(_, _) => "E0028",
+            ParseError::InMacro { error, .. } => error.code(),
         }
-
-        self.instructions.extend(other.instructions);
-        self.to_resolve.extend(to_resolve);
-        self.last_address += other.last_address;
     }
-}
 
-pub fn translate(text: &str) -> Result<TranslationOutput, ParseError> {
-    let mut tokenizer = Tokenizer::new(text);
-    let mut instructions = vec![];
-    let mut line_number = 1;
-    let mut current_addr = 0;
-    let mut labels = HashMap::new();
-    let mut branches: Vec<AddressResolving> = vec![];
-    while let Some(next) = tokenizer.next() {
-        match next {
-            Token::Add => parse!(
-                parse_add,
-                "ADD",
-                line_number,
-                tokenizer,
-                instructions,
-                current_addr
-            ),
-            Token::And => parse!(
-                parse_and,
-                "AND",
-                line_number,
-                tokenizer,
-                instructions,
-                current_addr
+    /// advice on how to fix the error
+    pub fn help(&self) -> Option<String> {
+        match self {
+            ParseError::LabelSyntaxError(label, _) => Some(format!(
+                "labels are defined with a trailing colon, as in \"{label}:\""
+            )),
+            ParseError::UnknownMnemonic {
+                suggestion: Some(suggestion),
+                ..
+            } => Some(format!("did you mean {suggestion:?}?")),
+            ParseError::ConstantUsedBeforeDefinition(_, _) => Some(
+                "this value must be known when the line is assembled; define the constant above this line"
+                    .to_owned(),
             ),
-            Token::Xor => parse!(
-                parse_xor,
-                "XOR",
-                line_number,
-                tokenizer,
-                instructions,
-                current_addr
-            ),
-            Token::Not => parse!(
-                parse_not,
-                "NOT",
-                line_number,
-                tokenizer,
-                instructions,
-                current_addr
+            ParseError::InvalidLocation(_) => Some(
+                "the location can only move forward, and .ORIG has to come first at an even address"
+                    .to_owned(),
             ),
-            Token::Jmp => parse!(
-                parse_jmp,
-                "JMP",
-                line_number,
-                tokenizer,
-                instructions,
-                current_addr
+            ParseError::IncludeNotFound(_, _) => Some(
+                "files are searched relative to the including file, then in the include directories"
+                    .to_owned(),
             ),
-            Token::Ret => {
-                let parse_ret = |_tokenizer: &mut Tokenizer| -> Result<u16, ()> { Ok(ret()) };
-                parse!(
-                    parse_ret,
-                    "RET",
-                    line_number,
-                    tokenizer,
-                    instructions,
-                    current_addr
-                )
+            ParseError::OddAddress(_) => {
+                Some("use `.ALIGN #2` to move it to the next word".to_owned())
             }
-            Token::Br(n, z, p) => {
-                if let Ok(label) = parse_br(&mut tokenizer) {
-                    branches.push(AddressResolving::Branch(Branch {
-                        current_addr,
-                        n,
-                        z,
-                        p,
-                        index: instructions.len(),
-                        label,
-                        line_number,
-                    }));
-                    current_addr += 1;
-                    // placeholder value
-                    instructions.push(PLACEHOLDER);
-                } else {
-                    return Err(ParseError::StatementSyntaxError(
-                        format!(
-                            "BR{}{}{}",
-                            if n { "n" } else { "" },
-                            if z { "z" } else { "" },
-                            if p { "p" } else { "" }
-                        ),
-                        line_number,
-                    ));
-                }
+            ParseError::DataInBss(_) => {
+                Some("reserve space with .BLKW, or put the data into .DATA".to_owned())
             }
-            Token::Lea => {
-                if let Ok((dest, label)) = parse_lea(&mut tokenizer) {
-                    branches.push(AddressResolving::Lea(LoadEffectiveAddress {
-                        current_addr,
-                        index: instructions.len(),
-                        label,
-                        dest,
-                        line_number,
-                    }));
-                    current_addr += 1;
-                    instructions.push(PLACEHOLDER);
-                } else {
-                    return Err(ParseError::StatementSyntaxError(
-                        "LEA".to_owned(),
-                        line_number,
-                    ));
-                }
+            ParseError::AlignmentOutsideText(_, _) => Some(
+                "sections are only placed at even addresses, so data can be aligned to words with `.ALIGN #2`, or to more in .TEXT"
+                    .to_owned(),
+            ),
+            ParseError::MisalignedField(_, _) => {
+                Some("add a DB field before it to move it to the next word".to_owned())
             }
-            Token::Jsr => {
-                if let Ok(label) = parse_jsr(&mut tokenizer) {
-                    branches.push(AddressResolving::Jsr(JumpSubroutine {
-                        current_addr,
-                        label,
-                        index: instructions.len(),
-                        line_number,
-                    }));
-                    current_addr += 1;
-                    instructions.push(PLACEHOLDER);
-                } else {
-                    return Err(ParseError::StatementSyntaxError(
-                        "JSR".to_owned(),
-                        line_number,
-                    ));
-                }
+            ParseError::InMacro { error, .. } => error.help(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::StatementSyntaxError(instruction, _) => {
+                write!(f, "syntax error while parsing {instruction:?}")
             }
-            Token::Lshf => {
-                if let Ok((dest, source, amount)) = parse_shift(&mut tokenizer) {
-                    instructions.push(lshf(dest, source, amount));
-                    current_addr += 1;
-                } else {
-                    return Err(ParseError::StatementSyntaxError(
-                        "LSHF".to_owned(),
-                        line_number,
-                    ));
+            ParseError::LabelSyntaxError(label, _) => {
+                write!(f, "syntax error while parsing label {label:?}")
+            }
+            ParseError::UnknownMnemonic { mnemonic, .. } => {
+                write!(f, "unknown mnemonic {mnemonic:?}")
+            }
+            ParseError::UnexpectedToken(token, _) => write!(f, "unexpected token {token}"),
+            ParseError::InvalidLocation(_) => write!(f, "cannot set location"),
+            ParseError::LexError(error, _) => write!(f, "{error}"),
+            ParseError::DuplicateLabel { label, .. } => {
+                write!(f, "label {label:?} is defined multiple times")
+            }
+            ParseError::DuplicateConstant { name, .. } => {
+                write!(f, "constant {name:?} is defined multiple times")
+            }
+            ParseError::RecursiveConstant(name, _) => {
+                write!(f, "constant {name:?} is defined in terms of itself")
+            }
+            ParseError::ConstantUsedBeforeDefinition(name, _) => {
+                write!(f, "{name:?} has to be defined before it is used here")
+            }
+            ParseError::InvalidExpression(error) => write!(f, "{error}"),
+            ParseError::DuplicateMacro { name, .. } => {
+                write!(f, "macro {name:?} is defined multiple times")
+            }
+            ParseError::UnterminatedMacro(name, _) => write!(f, "macro {name:?} is missing .ENDM"),
+            ParseError::UnterminatedStruct(name, _) => {
+                write!(f, "struct {name:?} is missing .ENDS")
+            }
+            ParseError::MisalignedField(field, _) => {
+                write!(f, "word field {field:?} is at an odd offset")
+            }
+            ParseError::MacroArguments {
+                name,
+                expected,
+                found,
+                ..
+            } => write!(
+                f,
+                "macro {name:?} takes {expected} arguments, but {found} were given"
+            ),
+            ParseError::RecursiveMacro(name, _) => write!(f, "macro {name:?} expands itself"),
+            ParseError::UnmatchedConditional(directive, _) => {
+                write!(f, "{directive} without a matching .IF")
+            }
+            ParseError::UnterminatedConditional(_) => write!(f, ".IF is missing .ENDIF"),
+            ParseError::IncludeNotFound(path, _) => write!(f, "cannot find {path:?} to include"),
+            ParseError::RecursiveInclude(path, _) => write!(f, "{path:?} includes itself"),
+            ParseError::OddAddress(_) => write!(f, "instruction or word at an odd address"),
+            ParseError::DataInBss(_) => write!(f, ".BSS cannot hold instructions or data"),
+            ParseError::AlignmentOutsideText(alignment, _) => {
+                write!(f, "cannot align to {alignment} bytes outside .TEXT")
+            }
+            ParseError::TrapRedefinition { name, vector, .. } => {
+                write!(f, "trap {name:?} is already defined as x{vector:02x}")
+            }
+            ParseError::InMacro { error, .. } => write!(f, "{error}"),
+            ParseError::ImmediateOutOfRange {
+                value,
+                min,
+                max,
+                field,
+                ..
+            } => write!(
+                f,
+                "{value} does not fit into {field}, expected a value from {min} to {max}"
+            ),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum LinkError {
+    LabelNotResolvedError {
+        label: String,
+        /// the defined label closest to `label`
+        suggestion: Option<String>,
+        span: Span,
+    },
+    OffsetOutOfRange {
+        label: String,
+        distance: i32,
+        min: i32,
+        max: i32,
+        span: Span,
+    },
+    DuplicateLabel {
+        label: String,
+        first: Span,
+        second: Span,
+    },
+    /// an immediate depending on labels, which turned out too large for its field
+    ImmediateOutOfRange {
+        value: i32,
+        min: i32,
+        max: i32,
+        field: &'static str,
+        span: Span,
+    },
+    /// an expression depending on labels that cannot be evaluated, like a division by zero
+    InvalidExpression(EvalError),
+    /// a PC offset to a label at an odd address, which it cannot express in words
+    UnalignedLabel { label: String, span: Span },
+    /// a section placed at an odd address or before the end of the sections preceding it
+    InvalidSectionAddress {
+        section: String,
+        addr: i32,
+        /// where the sections before it end
+        end: i32,
+        /// where the section is used first
+        span: Span,
+    },
+}
+
+impl LinkError {
+    /// position of the label reference that caused the error
+    pub fn span(&self) -> Span {
+        match self {
+            LinkError::LabelNotResolvedError { span, .. }
+            | LinkError::OffsetOutOfRange { span, .. }
+            | LinkError::DuplicateLabel { second: span, .. }
+            | LinkError::ImmediateOutOfRange { span, .. }
+            | LinkError::UnalignedLabel { span, .. }
+            | LinkError::InvalidSectionAddress { span, .. } => *span,
+            LinkError::InvalidExpression(error) => error.span(),
+        }
+    }
+
+    /// stable identifier of the kind of error, shared with [`ParseError::code`]
+    pub fn code(&self) -> &'static str {
+        match self {
+            LinkError::LabelNotResolvedError { .. } => "E0004",
+            LinkError::OffsetOutOfRange { .. } => "E0007",
+            LinkError::DuplicateLabel { .. } => "E0009",
+            LinkError::ImmediateOutOfRange { .. } => "E0006",
+            LinkError::InvalidExpression(_) => "E0014",
+            LinkError::UnalignedLabel { .. } => "E0024",
+            LinkError::InvalidSectionAddress { .. } => "E0026",
+        }
+    }
+
+    /// advice on how to fix the error
+    pub fn help(&self) -> Option<String> {
+        match self {
+            LinkError::LabelNotResolvedError {
+                suggestion: Some(suggestion),
+                ..
+            } => Some(format!("did you mean {suggestion:?}?")),
+            LinkError::LabelNotResolvedError { .. }
+            | LinkError::DuplicateLabel { .. }
+            | LinkError::ImmediateOutOfRange { .. }
+            | LinkError::InvalidExpression(_) => None,
+            LinkError::OffsetOutOfRange { .. } => Some(
+                "to reach labels further away, load the address into a register and use JMP or JSRR"
+                    .to_owned(),
+            ),
+            LinkError::UnalignedLabel { .. } => {
+                Some("use `.ALIGN #2` before the label to move it to the next word".to_owned())
+            }
+            LinkError::InvalidSectionAddress { end, .. } => Some(format!(
+                "sections have to start at an even address from x{end:04x} on"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LinkError::LabelNotResolvedError { label, .. } => {
+                write!(f, "could not resolve label {label:?} during link")
+            }
+            LinkError::OffsetOutOfRange {
+                label,
+                distance,
+                min,
+                max,
+                ..
+            } => write!(
+                f,
+                "label {label:?} is {distance} words away, but the offset has to be from {min} to {max}"
+            ),
+            LinkError::DuplicateLabel { label, .. } => {
+                write!(f, "label {label:?} is defined multiple times")
+            }
+            LinkError::ImmediateOutOfRange {
+                value,
+                min,
+                max,
+                field,
+                ..
+            } => write!(
+                f,
+                "{value} does not fit into {field}, expected a value from {min} to {max}"
+            ),
+            LinkError::InvalidExpression(error) => write!(f, "{error}"),
+            LinkError::UnalignedLabel { label, .. } => {
+                write!(f, "label {label:?} is at an odd address")
+            }
+            LinkError::InvalidSectionAddress { section, addr, .. } => {
+                write!(f, "section {section:?} cannot be placed at x{addr:04x}")
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Label {
+    /// byte offset in its section
+    addr: i32,
+    section: usize,
+    /// where the label is defined
+    span: Span,
+}
+
+/// the section of code and data outside of `.DATA` and `.BSS`, which starts at the origin
+const TEXT: &str = "text";
+/// the section reserving space without storing words
+const BSS: &str = "bss";
+
+/// a part of the output with its own location counter, like `.DATA`
+#[derive(Debug)]
+struct Section {
+    name: String,
+    /// the translated words, of which `.BSS` has none
+    words: Vec<u16>,
+    /// size in bytes, which is even once the translation unit is done
+    size: i32,
+    /// where the section is used first
+    span: Span,
+}
+
+impl Section {
+    fn new(name: &str, span: Span) -> Self {
+        Self {
+            name: name.to_owned(),
+            words: vec![],
+            size: 0,
+            span,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct TranslationOutput {
+    sections: Vec<Section>,
+    labels: HashMap<String, Label>,
+    to_resolve: Vec<AddressResolving>,
+    /// the symbols referred to by any translation unit
+    used: HashSet<String>,
+    /// byte address of the first word, set by `.ORIG` in the first translation unit
+    origin: i32,
+    /// labels defined by more than one translation unit, reported by `link`
+    redefinitions: Vec<LinkError>,
+    warnings: Vec<Warning>,
+}
+
+impl TranslationOutput {
+    /// warnings of all translation units, and labels none of them use
+    pub fn warnings(&self) -> Vec<Warning> {
+        // the labels of macro expansions are not written like this in the source
+        let mut unused: Vec<_> = self
+            .labels
+            .iter()
+            .filter(|(name, _)| !self.used.contains(*name) && !is_expanded_local(name))
+            .map(|(name, label)| Warning::UnusedLabel(name.to_owned(), label.span))
+            .collect();
+        unused.sort_by_key(|warning| (warning.span().file, warning.span().start));
+
+        self.warnings.iter().cloned().chain(unused).collect()
+    }
+
+    /// appends the sections of `other` to the ones of the same name, or after the others if new
+    pub fn extend(&mut self, other: TranslationOutput) {
+        // the index and byte offset here of every section of `other`
+        let mut placed = Vec::with_capacity(other.sections.len());
+        for section in other.sections {
+            match self
+                .sections
+                .iter_mut()
+                .position(|own| own.name == section.name)
+            {
+                Some(index) => {
+                    let own = &mut self.sections[index];
+                    placed.push((index, own.size));
+                    own.words.extend(section.words);
+                    own.size += section.size;
+                }
+                None => {
+                    placed.push((self.sections.len(), 0));
+                    self.sections.push(section);
                 }
             }
-            Token::Rshfl => {
-                if let Ok((dest, source, amount)) = parse_shift(&mut tokenizer) {
-                    instructions.push(rshfl(dest, source, amount));
-                    current_addr += 1;
-                } else {
-                    return Err(ParseError::StatementSyntaxError(
-                        "RSHFL".to_owned(),
-                        line_number,
-                    ));
+        }
+        // TODO make this more efficient maybe
+        // if it turns out to be slow
+        for (key, label) in other.labels {
+            match self.labels.entry(key) {
+                Entry::Occupied(entry) => self.redefinitions.push(LinkError::DuplicateLabel {
+                    label: entry.key().to_owned(),
+                    first: entry.get().span,
+                    second: label.span,
+                }),
+                Entry::Vacant(entry) => {
+                    let (section, offset) = placed[label.section];
+                    entry.insert(Label {
+                        addr: label.addr + offset,
+                        section,
+                        span: label.span,
+                    });
                 }
             }
-            Token::Rshfa => {
-                if let Ok((dest, source, amount)) = parse_shift(&mut tokenizer) {
-                    instructions.push(rshfa(dest, source, amount));
-                    current_addr += 1;
-                } else {
-                    return Err(ParseError::StatementSyntaxError(
-                        "RSHFA".to_owned(),
-                        line_number,
-                    ));
+        }
+        self.used.extend(other.used);
+        self.redefinitions.extend(other.redefinitions);
+        self.warnings.extend(other.warnings);
+
+        let mut to_resolve = Vec::with_capacity(other.to_resolve.len());
+        for res in other.to_resolve {
+            to_resolve.push(match res {
+                AddressResolving::Branch(mut branch) => {
+                    let (section, offset) = placed[branch.section];
+                    branch.section = section;
+                    branch.current_addr += offset;
+                    branch.index += offset as usize / 2;
+                    AddressResolving::Branch(branch)
                 }
+                AddressResolving::Lea(mut lea) => {
+                    let (section, offset) = placed[lea.section];
+                    lea.section = section;
+                    lea.current_addr += offset;
+                    lea.index += offset as usize / 2;
+                    AddressResolving::Lea(lea)
+                }
+                AddressResolving::Jsr(mut jsr) => {
+                    let (section, offset) = placed[jsr.section];
+                    jsr.section = section;
+                    jsr.current_addr += offset;
+                    jsr.index += offset as usize / 2;
+                    AddressResolving::Jsr(jsr)
+                }
+                AddressResolving::Expression(mut fixup) => {
+                    let (section, offset) = placed[fixup.section];
+                    fixup.section = section;
+                    fixup.current_addr += offset;
+                    fixup.index += offset as usize / 2;
+                    AddressResolving::Expression(fixup)
+                }
+            });
+        }
+
+        self.to_resolve.extend(to_resolve);
+    }
+}
+
+/// settings shared by [`translate_with`] and [`link_with`]
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// give up after this many errors instead of reporting all of them
+    pub error_limit: Option<usize>,
+    /// accept the syntax of classic LC-3 tools, see [`Tokenizer::classic`]
+    pub classic: bool,
+    /// constants defined before the input, like `-D DEBUG` on the command line.
+    /// definitions in the input with the same name are ignored
+    pub defines: Vec<(String, i32)>,
+    /// byte addresses to place sections at by name, like `--section data=x4000`.
+    /// the other sections follow the one before them, starting with the text section at the origin
+    pub sections: Vec<(String, i32)>,
+    /// named traps known besides the built-in ones, like `--trap SQRT=x30`
+    pub traps: Vec<(String, u8)>,
+}
+
+impl Options {
+    fn limit_reached(&self, errors: usize) -> bool {
+        matches!(self.error_limit, Some(limit) if errors >= limit)
+    }
+}
+
+struct Translator<'a> {
+    tokenizer: Tokenizer<'a>,
+    /// the words of the section being translated
+    instructions: Vec<u16>,
+    /// the sections used so far, except for the words of the one being translated
+    sections: Vec<Section>,
+    /// index of the section being translated
+    section: usize,
+    labels: HashMap<String, Label>,
+    to_resolve: Vec<AddressResolving>,
+    /// the symbols referred to so far, which tell the labels that are used
+    used: HashSet<String>,
+    current_addr: i32,
+    constants: HashMap<String, Constant>,
+    fixups: Vec<Fixup>,
+    warnings: Vec<Warning>,
+    /// byte address set by `.ORIG`, which `. = addr` is relative to
+    origin: i32,
+    /// whether `.ORIG` may still be used, as nothing has been emitted yet
+    origin_settable: bool,
+    /// set by `.END`, ignoring the rest of the input
+    ended: bool,
+    /// whether the next instruction can be reached from the previous ones
+    reachable: bool,
+    /// whether execution continues after the last instruction
+    falls_through: bool,
+    macros: HashMap<String, Macro>,
+    /// vectors of the traps usable by name like `HALT`, including those from `.TRAPDEF`
+    traps: HashMap<String, u8>,
+    /// whether trap names are in any case like mnemonics, as in the classic syntax
+    classic: bool,
+    /// number of macro expansions so far, which tells apart their local labels
+    expansions: usize,
+    /// the last label that is not local, which local labels like `@loop` belong to
+    scope: String,
+    /// the `.IF`s whose `.ENDIF` has not been reached yet, innermost last
+    conditionals: Vec<Conditional>,
+    /// constants given by [`Options::defines`], which take precedence over the source
+    predefined: HashSet<String>,
+    loader: &'a mut dyn Loader,
+    /// the file being translated
+    file: FileId,
+}
+
+/// an `.IF`, `.IFDEF` or `.IFNDEF` being translated
+struct Conditional {
+    span: Span,
+    /// whether its `.ELSE` has been reached
+    in_else: bool,
+}
+
+impl<'a> Translator<'a> {
+    fn statement(&mut self, next: Token) -> Result<(), ParseError> {
+        let span = self.tokenizer.span();
+        match next {
+            Token::Word(ref name)
+                if self.traps.contains_key(&self.trap_name(name))
+                    && !matches!(
+                        self.tokenizer.peek(),
+                        Some(Ok(Token::Colon | Token::Equals))
+                    ) =>
+            {
+                return self.named_trap(&self.trap_name(name), span);
             }
-            Token::Rti => {
-                instructions.push(return_from_interrupt());
-                current_addr += 1;
+            Token::Ret | Token::Jmp | Token::Br(true, true, true) => {
+                self.emitting(span, true)?;
+                self.instruction(span, true)
             }
-            Token::Trap => {
-                parse!(
-                    parse_trap,
-                    "TRAP",
-                    line_number,
-                    tokenizer,
-                    instructions,
-                    current_addr
-                )
+            Token::Add
+            | Token::And
+            | Token::Xor
+            | Token::Not
+            | Token::Lea
+            | Token::Br(_, _, _)
+            | Token::Lshf
+            | Token::Rshfl
+            | Token::Rshfa
+            | Token::Rti
+            | Token::Ldb
+            | Token::Ldw
+            | Token::Stb
+            | Token::Stw
+            | Token::Jsr
+            | Token::Jsrr
+            | Token::Nop
+            | Token::Mov
+            | Token::Clr
+            | Token::Neg
+            | Token::Sub
+            | Token::Inc
+            | Token::Dec
+            | Token::Push
+            | Token::Pop
+            | Token::Ldi16
+            | Token::Trap => {
+                self.emitting(span, true)?;
+                self.instruction(span, false)
             }
-            Token::Halt => {
-                let parse_halt = |_tokenizer: &mut Tokenizer| -> Result<u16, ()> { Ok(trap(0x25)) };
-                parse!(
-                    parse_halt,
-                    "HALT",
-                    line_number,
-                    tokenizer,
-                    instructions,
-                    current_addr
-                )
+            Token::DefineWords => {
+                self.emitting(span, true)?;
+                self.data(span)
             }
-            Token::Getc => {
-                let parse_getc = |_tokenizer: &mut Tokenizer| -> Result<u16, ()> { Ok(trap(0x20)) };
-                parse!(
-                    parse_getc,
-                    "GETC",
-                    line_number,
-                    tokenizer,
-                    instructions,
-                    current_addr
-                )
+            Token::DefineBytes | Token::DefineBytesZero => {
+                self.emitting(span, false)?;
+                self.data(span)
             }
-            Token::Out => {
-                let parse_out = |_tokenizer: &mut Tokenizer| -> Result<u16, ()> { Ok(trap(0x21)) };
-                parse!(
-                    parse_out,
-                    "OUT",
-                    line_number,
-                    tokenizer,
-                    instructions,
-                    current_addr
-                )
+            _ => {}
+        }
+        if !matches!(
+            next,
+            Token::Linebreak | Token::Semicolon | Token::Period | Token::Word(_)
+        ) {
+            self.origin_settable = false;
+        }
+
+        match next {
+            Token::Add => parse!(self, parse_add, "ADD"),
+            Token::And => parse!(self, parse_and, "AND"),
+            Token::Xor => parse!(self, parse_xor, "XOR"),
+            Token::Not => parse!(self, parse_not, "NOT"),
+            Token::Jmp => parse!(self, parse_jmp, "JMP"),
+            Token::Ret => {
+                let parse_ret =
+                    |_operands: &mut Operands| -> Result<u16, OperandError> { Ok(ret()) };
+                parse!(self, parse_ret, "RET")
             }
-            Token::Puts => {
-                let parse_puts = |_tokenizer: &mut Tokenizer| -> Result<u16, ()> { Ok(trap(0x22)) };
-                parse!(
-                    parse_puts,
-                    "PUTS",
-                    line_number,
-                    tokenizer,
-                    instructions,
-                    current_addr
-                )
+            Token::Br(n, z, p) => {
+                let name = format!(
+                    "BR{}{}{}",
+                    if n { "n" } else { "" },
+                    if z { "z" } else { "" },
+                    if p { "p" } else { "" }
+                );
+                let instruction = match self.operands(&name, parse_br)? {
+                    Target::Offset(offset) => branch(n, z, p, offset as i16),
+                    Target::Address(target, span) => {
+                        self.to_resolve.push(AddressResolving::Branch(Branch {
+                            current_addr: self.current_addr,
+                            section: self.section,
+                            n,
+                            z,
+                            p,
+                            index: self.instructions.len(),
+                            label: target.to_string(),
+                            target,
+                            span,
+                        }));
+                        PLACEHOLDER
+                    }
+                };
+                self.current_addr += 2;
+                self.instructions.push(instruction);
             }
-            Token::In => {
-                let parse_in = |_tokenizer: &mut Tokenizer| -> Result<u16, ()> { Ok(trap(0x23)) };
-                parse!(
-                    parse_in,
-                    "IN",
-                    line_number,
-                    tokenizer,
-                    instructions,
-                    current_addr
-                )
+            Token::Lea => {
+                let instruction = match self.operands("LEA", parse_lea)? {
+                    (dest, Target::Offset(offset)) => lea(dest, offset as i16),
+                    (dest, Target::Address(target, span)) => {
+                        self.to_resolve
+                            .push(AddressResolving::Lea(LoadEffectiveAddress {
+                                current_addr: self.current_addr,
+                                section: self.section,
+                                index: self.instructions.len(),
+                                label: target.to_string(),
+                                target,
+                                dest,
+                                span,
+                            }));
+                        PLACEHOLDER
+                    }
+                };
+                self.current_addr += 2;
+                self.instructions.push(instruction);
+            }
+            Token::Jsr => {
+                let instruction = match self.operands("JSR", parse_jsr)? {
+                    Target::Offset(offset) => jsr(offset),
+                    Target::Address(target, span) => {
+                        self.to_resolve.push(AddressResolving::Jsr(JumpSubroutine {
+                            current_addr: self.current_addr,
+                            section: self.section,
+                            label: target.to_string(),
+                            target,
+                            index: self.instructions.len(),
+                            span,
+                        }));
+                        PLACEHOLDER
+                    }
+                };
+                self.current_addr += 2;
+                self.instructions.push(instruction);
+            }
+            Token::Lshf => {
+                let (dest, source, amount) = self.operands("LSHF", parse_shift)?;
+                self.instructions.push(lshf(dest, source, amount));
+                self.current_addr += 2;
+            }
+            Token::Rshfl => {
+                let (dest, source, amount) = self.operands("RSHFL", parse_shift)?;
+                self.instructions.push(rshfl(dest, source, amount));
+                self.current_addr += 2;
+            }
+            Token::Rshfa => {
+                let (dest, source, amount) = self.operands("RSHFA", parse_shift)?;
+                self.instructions.push(rshfa(dest, source, amount));
+                self.current_addr += 2;
+            }
+            Token::Rti => {
+                self.instructions.push(return_from_interrupt());
+                self.current_addr += 2;
+            }
+            Token::Trap => {
+                parse!(self, parse_trap, "TRAP")
             }
             Token::Stb => {
-                parse!(
-                    parse_stb,
-                    "STB",
-                    line_number,
-                    tokenizer,
-                    instructions,
-                    current_addr
-                )
+                parse!(self, parse_stb, "STB")
             }
             Token::Stw => {
-                parse!(
-                    parse_stw,
-                    "STW",
-                    line_number,
-                    tokenizer,
-                    instructions,
-                    current_addr
-                )
+                parse!(self, parse_stw, "STW")
             }
             Token::Ldb => {
-                parse!(
-                    parse_ldb,
-                    "LDB",
-                    line_number,
-                    tokenizer,
-                    instructions,
-                    current_addr
-                )
+                parse!(self, parse_ldb, "LDB")
             }
             Token::Ldw => {
-                parse!(
-                    parse_ldw,
-                    "LDW",
-                    line_number,
-                    tokenizer,
-                    instructions,
-                    current_addr
-                )
+                parse!(self, parse_ldw, "LDW")
             }
             Token::Jsrr => {
-                parse!(
-                    parse_jsrr,
-                    "JSRR",
-                    line_number,
-                    tokenizer,
-                    instructions,
-                    current_addr
-                )
+                parse!(self, parse_jsrr, "JSRR")
             }
 
+            // pseudo-instructions
+            Token::Nop => {
+                let parse_nop =
+                    |_operands: &mut Operands| -> Result<u16, OperandError> { Ok(nop()) };
+                parse!(self, parse_nop, "NOP")
+            }
+            Token::Mov => parse!(self, parse_mov, "MOV"),
+            Token::Clr => {
+                let parse_clr = |operands: &mut Operands| parse_register(operands).map(clr);
+                parse!(self, parse_clr, "CLR")
+            }
+            Token::Neg => parse_words!(self, parse_neg, "NEG"),
+            Token::Sub => parse_words!(self, parse_sub, "SUB"),
+            Token::Inc => {
+                let parse_inc = |operands: &mut Operands| parse_register(operands).map(inc);
+                parse!(self, parse_inc, "INC")
+            }
+            Token::Dec => {
+                let parse_dec = |operands: &mut Operands| parse_register(operands).map(dec);
+                parse!(self, parse_dec, "DEC")
+            }
+            Token::Push => {
+                let parse_push = |operands: &mut Operands| parse_register(operands).map(push);
+                parse_words!(self, parse_push, "PUSH")
+            }
+            Token::Pop => {
+                let parse_pop = |operands: &mut Operands| parse_register(operands).map(pop);
+                parse_words!(self, parse_pop, "POP")
+            }
+            Token::Ldi16 => parse_words!(self, parse_ldi16, "LDI16"),
+
             // static memory
-            Token::DefineBytes => match parse_define_bytes(&mut tokenizer) {
-                Ok(words) => {
-                    for word in words {
-                        instructions.push(word);
-                        current_addr += 1;
-                    }
-                }
-                Err(()) => {
-                    return Err(ParseError::StatementSyntaxError(
-                        "DB".to_owned(),
-                        line_number,
-                    ))
-                }
-            },
-            Token::DefineWords => match parse_define_words(&mut tokenizer) {
-                Ok(words) => {
-                    for word in words {
-                        instructions.push(word);
-                        current_addr += 1;
-                    }
-                }
-                Err(()) => {
-                    return Err(ParseError::StatementSyntaxError(
-                        "DW".to_owned(),
-                        line_number,
-                    ))
+            Token::DefineBytes => {
+                let bytes = self.operands("DB", |operands| parse_define_bytes(operands, false))?;
+                self.emit_bytes(bytes);
+            }
+            Token::DefineBytesZero => {
+                let bytes = self.operands("DBZ", |operands| parse_define_bytes(operands, true))?;
+                self.emit_bytes(bytes);
+            }
+            Token::DefineWords => parse_words!(self, parse_define_words, "DW"),
+
+            Token::Period => match next_token(&mut self.tokenizer)
+                .map_err(|error| self.operand_error(".", error))?
+            {
+                Some(Token::Equals) => {
+                    self.origin_settable = false;
+                    let addr = self.operands(".", parse_address)?;
+                    self.set_location(addr, span)?;
                 }
+                Some(Token::Word(name)) => self.directive(&name, span)?,
+                _ => return Err(self.operand_error(".", OperandError::Syntax)),
             },
 
-            Token::Period => {
-                if let Ok(skip_to) = parse_set_loc(&mut tokenizer) {
-                    if skip_to < current_addr {
-                        return Err(ParseError::InvalidLocation(line_number));
-                    }
-                    assert!(
-                        skip_to >= current_addr,
-                        ". = : skip should be after current address; TODO implement error"
-                    );
-                    instructions.extend(vec![0x0; (skip_to - current_addr) as usize]);
-                    current_addr = skip_to;
-                } else {
-                    return Err(ParseError::StatementSyntaxError(
-                        ".".to_owned(),
-                        line_number,
-                    ));
-                }
-            }
-
-            Token::Semicolon => eat_comment(&mut tokenizer),
-            Token::Linebreak => {
-                line_number += 1;
+            Token::Semicolon => eat_comment(&mut self.tokenizer),
+            Token::Linebreak => {}
+            Token::Word(name)
+                if self.macros.contains_key(&name)
+                    && !matches!(
+                        self.tokenizer.peek(),
+                        Some(Ok(Token::Colon | Token::Equals))
+                    ) =>
+            {
+                self.expand_macro(name, span)?;
             }
             Token::Word(label) => {
-                if let Some(Token::Colon) = tokenizer.next() {
-                    labels.insert(label, current_addr);
+                let after = next_token(&mut self.tokenizer)
+                    .map_err(|error| self.operand_error(&label, error))?;
+                if let (Some(Token::Equals), true) = (&after, is_symbol(&label)) {
+                    let value = self.operands(&label, |operands| {
+                        let value = operands.next_token()?;
+                        operands.expr(value)
+                    })?;
+                    self.define_constant(label, value, span)?;
+                } else if let Some(Token::Colon) = after {
+                    // the generated labels of macro expansions contain a `.` and keep the scope
+                    if !is_local(&label) && !label.contains('.') {
+                        self.scope.clone_from(&label);
+                    }
+                    let label = scoped(&self.scope, label);
+                    if let Some(constant) = self.constants.get(&label) {
+                        return Err(ParseError::DuplicateLabel {
+                            label,
+                            first: constant.span,
+                            second: span,
+                        });
+                    }
+                    self.origin_settable = false;
+                    match self.labels.entry(label) {
+                        Entry::Occupied(entry) => {
+                            return Err(ParseError::DuplicateLabel {
+                                label: entry.key().to_owned(),
+                                first: entry.get().span,
+                                second: span,
+                            });
+                        }
+                        Entry::Vacant(entry) => {
+                            entry.insert(Label {
+                                addr: self.current_addr,
+                                section: self.section,
+                                span,
+                            });
+                        }
+                    }
+                    self.reachable = true;
                 } else {
-                    return Err(ParseError::LabelSyntaxError(label, line_number));
+                    // a lone word is most likely a label missing its colon,
+                    // unless it looks like a misspelled mnemonic
+                    let names = self.traps.keys().map(String::as_str);
+                    let suggestion =
+                        closest(&label, MNEMONICS.into_iter().chain(names)).map(str::to_owned);
+                    return Err(match after {
+                        None | Some(Token::Linebreak | Token::Semicolon)
+                            if suggestion.is_none() =>
+                        {
+                            ParseError::LabelSyntaxError(label, span)
+                        }
+                        _ => ParseError::UnknownMnemonic {
+                            mnemonic: label,
+                            suggestion,
+                            span,
+                        },
+                    });
                 }
             }
             Token::Comma
@@ -776,75 +1894,1018 @@ pub fn translate(text: &str) -> Result<TranslationOutput, ParseError> {
             | Token::Number(_)
             | Token::Register(_)
             | Token::Colon
-            | Token::Str(_) => {
+            | Token::Str(_)
+            | Token::Plus
+            | Token::Minus
+            | Token::Star
+            | Token::Slash
+            | Token::Percent
+            | Token::Ampersand
+            | Token::Pipe
+            | Token::Caret
+            | Token::Tilde
+            | Token::ShiftLeft
+            | Token::ShiftRight
+            | Token::LeftParen
+            | Token::RightParen
+            | Token::Dollar => {
                 return Err(ParseError::UnexpectedToken(
                     format!("{next:?}"),
-                    line_number,
+                    self.tokenizer.span(),
                 ));
             }
         }
+        Ok(())
+    }
+
+    /// moves the location counter forward to the byte address `addr`, filling the gap with zeros
+    fn set_location(&mut self, addr: i32, span: Span) -> Result<(), ParseError> {
+        let skip_to = addr - self.origin;
+        // only the text section is known to start at the origin
+        if self.section != 0 || skip_to < self.current_addr {
+            return Err(ParseError::InvalidLocation(self.tokenizer.span()));
+        }
+        let words = (skip_to - self.current_addr) / 2;
+        if words > LARGE_GAP {
+            self.warnings.push(Warning::LocationGap { words, span });
+        }
+        self.skip_to(skip_to);
+        Ok(())
+    }
+
+    /// moves the location counter forward to the byte offset `offset`, filling the gap with zeros
+    fn skip_to(&mut self, offset: i32) {
+        // a half filled word already has a zero upper byte
+        if !self.in_bss() {
+            self.instructions.resize(((offset + 1) / 2) as usize, 0);
+        }
+        self.current_addr = offset;
+    }
+
+    /// appends `bytes` at the location counter, which may be at an odd address
+    fn emit_bytes(&mut self, bytes: Vec<u8>) {
+        for byte in bytes {
+            if self.current_addr % 2 == 0 {
+                self.instructions.push(byte as u16);
+            } else if let Some(word) = self.instructions.last_mut() {
+                *word |= (byte as u16) << 8;
+            }
+            self.current_addr += 1;
+        }
+    }
+
+    /// checks that the section can hold data, and that `words` start at an even address
+    fn emitting(&self, span: Span, words: bool) -> Result<(), ParseError> {
+        if self.in_bss() {
+            return Err(ParseError::DataInBss(span));
+        }
+        if words && self.current_addr % 2 != 0 {
+            return Err(ParseError::OddAddress(span));
+        }
+        Ok(())
+    }
+
+    fn in_bss(&self) -> bool {
+        self.sections[self.section].name == BSS
+    }
+
+    /// continues with the section `name` where it was left, starting it at `span` if it is new
+    fn switch_section(&mut self, name: &str, span: Span) {
+        let index = match self
+            .sections
+            .iter()
+            .position(|section| section.name == name)
+        {
+            Some(index) => index,
+            None => {
+                self.sections.push(Section::new(name, span));
+                self.sections.len() - 1
+            }
+        };
+        self.store_section();
+        self.section = index;
+        self.instructions = std::mem::take(&mut self.sections[index].words);
+        self.current_addr = self.sections[index].size;
+        // execution does not continue from one section into another
+        self.reachable = true;
+        self.falls_through = false;
+    }
+
+    /// moves the words of the section being translated into `sections`
+    fn store_section(&mut self) {
+        let section = &mut self.sections[self.section];
+        section.words = std::mem::take(&mut self.instructions);
+        section.size = self.current_addr;
+    }
+
+    /// translates the directive `.name` starting at `span`
+    fn directive(&mut self, name: &str, span: Span) -> Result<(), ParseError> {
+        let directive = format!(".{}", name.to_ascii_uppercase());
+        let emitted = match directive.as_str() {
+            ".ORIG" => {
+                let addr = self.operands(".ORIG", parse_address)?;
+                if !self.origin_settable || addr % 2 != 0 {
+                    return Err(ParseError::InvalidLocation(self.tokenizer.span()));
+                }
+                self.origin_settable = false;
+                self.origin = addr;
+                return Ok(());
+            }
+            ".END" => {
+                self.ended = true;
+                return Ok(());
+            }
+            ".TRAPDEF" => {
+                let (name, vector) = self.operands(".TRAPDEF", parse_trapdef)?;
+                return self.define_trap(name, vector, span);
+            }
+            ".EQU" => {
+                let (name, value) = self.operands(".EQU", parse_equ)?;
+                return self.define_constant(name, value, span);
+            }
+            ".MACRO" => return self.define_macro(span),
+            ".INCLUDE" => {
+                let (path, path_span) = self.operands(".INCLUDE", parse_include)?;
+                return self.include(path, path_span);
+            }
+            ".IF" => {
+                let condition = match self.operands(".IF", parse_if) {
+                    Ok(condition) => condition,
+                    Err(error) => {
+                        // a broken condition counts as false, so its `.ELSE` and `.ENDIF`
+                        // still match and only the `.IF` itself is reported
+                        self.synchronize();
+                        self.conditional(false, span)?;
+                        return Err(error);
+                    }
+                };
+                return self.conditional(condition, span);
+            }
+            ".IFDEF" | ".IFNDEF" => {
+                let name = self.operands(&directive, parse_ifdef)?;
+                self.used.insert(name.clone());
+                let defined = self.constants.contains_key(&name) || self.labels.contains_key(&name);
+                return self.conditional(defined == (directive == ".IFDEF"), span);
+            }
+            ".ELSE" => {
+                self.operands(".ELSE", parse_line_end)?;
+                match self.conditionals.last_mut() {
+                    Some(conditional) if !conditional.in_else => conditional.in_else = true,
+                    _ => return Err(ParseError::UnmatchedConditional(directive, span)),
+                }
+                if self.skip_branch(false).is_some() {
+                    self.conditionals.pop();
+                }
+                return Ok(());
+            }
+            ".ENDIF" => {
+                if self.conditionals.pop().is_none() {
+                    return Err(ParseError::UnmatchedConditional(directive, span));
+                }
+                return Ok(());
+            }
+            ".ENDM" | ".ENDS" => return Err(ParseError::StatementSyntaxError(directive, span)),
+            ".STRUCT" => return self.define_struct(span),
+            ".ALIGN" => {
+                let alignment = self.operands(".ALIGN", parse_align)?;
+                // only the text section is known to start at the origin
+                if alignment > 2 && self.section != 0 {
+                    return Err(ParseError::AlignmentOutsideText(alignment, span));
+                }
+                let addr = self.origin + self.current_addr;
+                self.skip_to(self.current_addr + (alignment - addr % alignment) % alignment);
+                return Ok(());
+            }
+            ".TEXT" | ".DATA" | ".BSS" => {
+                self.operands(&directive, parse_line_end)?;
+                self.switch_section(&directive[1..].to_ascii_lowercase(), span);
+                return Ok(());
+            }
+            ".FILL" | ".BLKW" | ".STRINGZ" if self.current_addr % 2 != 0 => {
+                return Err(ParseError::OddAddress(span))
+            }
+            ".FILL" | ".STRINGZ" if self.in_bss() => return Err(ParseError::DataInBss(span)),
+            ".FILL" => vec![self.operands(".FILL", parse_fill)?],
+            ".BLKW" if self.in_bss() => {
+                let count = self.operands(".BLKW", parse_blkw)?;
+                self.skip_to(self.current_addr + 2 * count as i32);
+                return Ok(());
+            }
+            ".BLKW" => vec![0; self.operands(".BLKW", parse_blkw)?],
+            ".STRINGZ" => self.operands(".STRINGZ", parse_stringz)?,
+            _ => {
+                return Err(ParseError::UnknownMnemonic {
+                    suggestion: closest(&directive, DIRECTIVES).map(str::to_owned),
+                    mnemonic: directive,
+                    span,
+                })
+            }
+        };
+
+        self.origin_settable = false;
+        self.data(span);
+        self.current_addr += 2 * emitted.len() as i32;
+        self.instructions.extend(emitted);
+        Ok(())
+    }
+
+    /// keeps track of the control flow for [`Warning::FallIntoData`]
+    fn data(&mut self, span: Span) {
+        if self.falls_through {
+            self.warnings.push(Warning::FallIntoData(span));
+        }
+        self.falls_through = false;
+    }
+
+    /// keeps track of the control flow for [`Warning::UnreachableCode`] and [`Warning::FallIntoData`]
+    fn instruction(&mut self, span: Span, jumps_away: bool) {
+        if !self.reachable {
+            self.warnings.push(Warning::UnreachableCode(span));
+        }
+        self.reachable = !jumps_away;
+        self.falls_through = !jumps_away;
+    }
+
+    /// parses the operands of the statement `name` with `parse`,
+    /// keeping its fixups for the words it is about to emit
+    fn operands<T>(
+        &mut self,
+        name: &str,
+        parse: impl FnOnce(&mut Operands) -> Result<T, OperandError>,
+    ) -> Result<T, ParseError> {
+        let mut operands = Operands {
+            tokenizer: &mut self.tokenizer,
+            constants: &self.constants,
+            labels: &self.labels,
+            scope: &self.scope,
+            origin: self.origin,
+            section: self.section,
+            current_addr: self.current_addr,
+            fixups: vec![],
+            used: &mut self.used,
+        };
+        let result = parse(&mut operands);
+        let fixups = operands.fixups;
+        let parsed = result.map_err(|error| self.operand_error(name, error))?;
+
+        // the word holding the current address, which is half filled at an odd address
+        let start = (self.current_addr / 2) as usize;
+        self.fixups.extend(fixups.into_iter().map(|fixup| Fixup {
+            index: start + fixup.index,
+            ..fixup
+        }));
+        Ok(parsed)
+    }
+
+    /// translates the use of a named trap like `HALT`, which takes no operands
+    fn named_trap(&mut self, name: &str, span: Span) -> Result<(), ParseError> {
+        let vector = self.traps[name];
+        self.emitting(span, true)?;
+        self.instruction(span, vector == HALT_VECTOR);
+        self.origin_settable = false;
+        let parse_named =
+            |_operands: &mut Operands| -> Result<u16, OperandError> { Ok(trap(vector)) };
+        parse!(self, parse_named, name);
+        Ok(())
+    }
+
+    /// the key of the trap `name` in [`Translator::traps`], which is uppercase in the classic syntax
+    fn trap_name(&self, name: &str) -> String {
+        if self.classic {
+            name.to_ascii_uppercase()
+        } else {
+            name.to_owned()
+        }
+    }
+
+    /// makes `name` a mnemonic for `TRAP vector`, unless it names another trap already
+    fn define_trap(&mut self, name: String, vector: u8, span: Span) -> Result<(), ParseError> {
+        let name = self.trap_name(&name);
+        match self.traps.get(&name) {
+            Some(&defined) if defined != vector => Err(ParseError::TrapRedefinition {
+                name,
+                vector: defined,
+                span,
+            }),
+            _ => {
+                self.traps.insert(name, vector);
+                Ok(())
+            }
+        }
+    }
+
+    /// defines the constant `name`, which must not collide with a label or constant
+    fn define_constant(&mut self, name: String, value: Expr, span: Span) -> Result<(), ParseError> {
+        if self.predefined.contains(&name) {
+            return Ok(());
+        }
+        let first = self
+            .constants
+            .get(&name)
+            .map(|constant| constant.span)
+            .or_else(|| self.labels.get(&name).map(|label| label.span));
+        if let Some(first) = first {
+            return Err(ParseError::DuplicateConstant {
+                name,
+                first,
+                second: span,
+            });
+        }
+        self.constants.insert(name, Constant { value, span });
+        Ok(())
+    }
+
+    /// splices in the file `path` written at `span`, unless it has been translated before
+    fn include(&mut self, path: String, span: Span) -> Result<(), ParseError> {
+        let Some((file, text)) = self.loader.load(&path, span.file) else {
+            return Err(ParseError::IncludeNotFound(path, span));
+        };
+        if file == self.file || self.tokenizer.includes().any(|including| including == file) {
+            return Err(ParseError::RecursiveInclude(path, span));
+        }
+        if self.loader.translate_once(file) {
+            self.tokenizer.include(file, &text, span);
+        }
+        Ok(())
+    }
+
+    /// enters the `.IF` at `span`, skipping to its `.ELSE` or `.ENDIF` unless `condition` holds
+    fn conditional(&mut self, condition: bool, span: Span) -> Result<(), ParseError> {
+        self.conditionals.push(Conditional {
+            span,
+            in_else: false,
+        });
+        if !condition {
+            match self.skip_branch(true) {
+                Some(true) => self.conditionals.last_mut().unwrap().in_else = true,
+                Some(false) => _ = self.conditionals.pop(),
+                // reported as unterminated at the end of the input
+                None => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// skips the lines of a branch that is not taken up to the `.ENDIF` of the current `.IF`,
+    /// or its `.ELSE` if `to_else` is set. returns whether it stopped at `.ELSE`,
+    /// or `None` at the end of the input
+    fn skip_branch(&mut self, to_else: bool) -> Option<bool> {
+        let mut depth = 0;
+        loop {
+            match self.tokenizer.skip_line()?.as_str() {
+                ".IF" | ".IFDEF" | ".IFNDEF" => depth += 1,
+                ".ELSE" if depth == 0 && to_else => return Some(true),
+                ".ENDIF" if depth == 0 => return Some(false),
+                ".ENDIF" => depth -= 1,
+                _ => {}
+            }
+        }
+    }
+
+    /// reads the definition of a macro up to `.ENDM`, starting after `.MACRO`
+    fn define_macro(&mut self, span: Span) -> Result<(), ParseError> {
+        let (name, params) = self.operands(".MACRO", parse_macro)?;
+        let mut body = vec![];
+        loop {
+            let line_start = self.tokenizer.at_line_start();
+            let Some(token) = self.tokenizer.next() else {
+                return Err(ParseError::UnterminatedMacro(name, span));
+            };
+            match token {
+                Ok(Token::Semicolon) => {
+                    eat_comment(&mut self.tokenizer);
+                    body.push((Ok(Token::Linebreak), self.tokenizer.span()));
+                }
+                Ok(Token::Period) if line_start => {
+                    let period = self.tokenizer.span();
+                    if let Some(Ok(Token::Word(word))) = self.tokenizer.peek() {
+                        if word.eq_ignore_ascii_case("ENDM") {
+                            self.tokenizer.next();
+                            break;
+                        }
+                    }
+                    body.push((Ok(Token::Period), period));
+                }
+                token => body.push((token, self.tokenizer.span())),
+            }
+        }
+
+        if let Some(first) = self.macros.get(&name) {
+            return Err(ParseError::DuplicateMacro {
+                name,
+                first: first.span,
+                second: span,
+            });
+        }
+        self.macros.insert(name, Macro { params, body, span });
+        Ok(())
+    }
+
+    /// reads the fields of `.STRUCT name` up to `.ENDS`, defining constants for their offsets
+    /// like `name.next`, and for the size as `name.size`.
+    /// all of them count bytes like addresses, so the offsets of LDW and STW,
+    /// which count words, are written like `name.next >> #1`
+    fn define_struct(&mut self, span: Span) -> Result<(), ParseError> {
+        let name = self.operands(".STRUCT", parse_struct)?;
+        let mut fields = vec![];
+        let mut size = 0;
+        // the first error, reported once the fields are skipped up to `.ENDS`
+        let mut error = None;
+        loop {
+            let line_start = self.tokenizer.at_line_start();
+            let Some(token) = self.tokenizer.next() else {
+                return Err(ParseError::UnterminatedStruct(name, span));
+            };
+            let field_span = self.tokenizer.span();
+            let result = match token {
+                Ok(Token::Linebreak) => Ok(()),
+                Ok(Token::Semicolon) => {
+                    eat_comment(&mut self.tokenizer);
+                    Ok(())
+                }
+                Ok(Token::Period) if line_start => {
+                    if let Some(Ok(Token::Word(word))) = self.tokenizer.peek() {
+                        if word.eq_ignore_ascii_case("ENDS") {
+                            self.tokenizer.next();
+                            self.operands(".ENDS", parse_line_end)?;
+                            break;
+                        }
+                    }
+                    Err(ParseError::StatementSyntaxError(
+                        ".STRUCT".to_owned(),
+                        field_span,
+                    ))
+                }
+                Ok(Token::Word(field)) if is_symbol(&field) => self
+                    .operands(&field, parse_field)
+                    .and_then(|(words, count)| {
+                        let field = format!("{name}.{field}");
+                        if words && size % 2 != 0 {
+                            return Err(ParseError::MisalignedField(field, field_span));
+                        }
+                        fields.push((field, size, field_span));
+                        size += if words { 2 * count } else { count };
+                        Ok(())
+                    }),
+                Ok(_) => Err(ParseError::StatementSyntaxError(
+                    ".STRUCT".to_owned(),
+                    field_span,
+                )),
+                Err(lex_error) => Err(ParseError::LexError(lex_error, field_span)),
+            };
+            if let Err(field_error) = result {
+                error.get_or_insert(field_error);
+                self.synchronize();
+            }
+        }
+        if let Some(error) = error {
+            return Err(error);
+        }
+
+        fields.push((format!("{name}.size"), size, span));
+        for (field, offset, span) in fields {
+            self.define_constant(field, Expr::Number(offset), span)?;
+        }
+        Ok(())
+    }
+
+    /// reads the arguments of the macro `name` used at `span` and expands it
+    fn expand_macro(&mut self, name: String, span: Span) -> Result<(), ParseError> {
+        if self.tokenizer.expansions().any(|(outer, _)| outer == name) {
+            return Err(ParseError::RecursiveMacro(name, span));
+        }
+        let args = self.operands(&name, parse_macro_args)?;
+        let macro_ = &self.macros[&name];
+        if args.len() != macro_.params.len() {
+            return Err(ParseError::MacroArguments {
+                expected: macro_.params.len(),
+                found: args.len(),
+                name,
+                span,
+            });
+        }
+        self.expansions += 1;
+        let tokens = macro_.expand(&args, &format!("{}.{}", span.file, self.expansions));
+        self.tokenizer.expand(name, span, tokens);
+        Ok(())
+    }
+
+    /// attaches the macro expansions the last token is part of to `error`
+    fn in_expansions(&self, error: ParseError) -> ParseError {
+        self.tokenizer
+            .expansions()
+            .fold(error, |error, (name, call)| ParseError::InMacro {
+                error: Box::new(error),
+                name: name.to_owned(),
+                call,
+            })
+    }
+
+    /// fills in the constants of the targets of BR, LEA and JSR,
+    /// whose PC offsets `link` computes from the addresses
+    fn resolve_targets(&mut self) -> Vec<ParseError> {
+        let mut failed = vec![];
+        for resolving in &mut self.to_resolve {
+            let (target, span) = match resolving {
+                AddressResolving::Branch(branch) => (&mut branch.target, branch.span),
+                AddressResolving::Lea(lea) => (&mut lea.target, lea.span),
+                AddressResolving::Jsr(jsr) => (&mut jsr.target, jsr.span),
+                AddressResolving::Expression(_) => continue,
+            };
+            match target.substitute(&self.constants) {
+                Ok(substituted) => *target = substituted,
+                Err(error) => failed.push((error, span)),
+            }
+        }
+        failed
+            .into_iter()
+            .map(|(error, span)| self.operand_error_at("", OperandError::Eval(error), span))
+            .collect()
+    }
+
+    /// fills in the constants that were not defined yet when they were used,
+    /// leaving the expressions depending on labels or `$` to `link`
+    fn resolve_fixups(&mut self) -> Vec<ParseError> {
+        let mut errors = vec![];
+        for fixup in std::mem::take(&mut self.fixups) {
+            let substituted = match fixup.value.substitute(&self.constants) {
+                Ok(substituted) => substituted,
+                Err(error) => {
+                    errors.push(self.operand_error_at("", OperandError::Eval(error), fixup.span));
+                    continue;
+                }
+            };
+            let scope = Scope {
+                constants: &self.constants,
+                label: &|_| None,
+                location: None,
+            };
+            let value = match substituted.evaluate(&scope) {
+                Ok(value) => value,
+                Err(EvalError::Undefined(_, _) | EvalError::UnknownLocation(_)) => {
+                    self.to_resolve.push(AddressResolving::Expression(Fixup {
+                        value: substituted,
+                        ..fixup
+                    }));
+                    continue;
+                }
+                Err(error) => {
+                    errors.push(ParseError::InvalidExpression(error));
+                    continue;
+                }
+            };
+            if let Err(error) = fixup.field.check(value) {
+                errors.push(self.operand_error_at("", error, fixup.span));
+                continue;
+            }
+            self.sections[fixup.section].words[fixup.index] |=
+                fixup.field.encode(value) << fixup.shift;
+        }
+        errors
+    }
+
+    fn operand_error(&self, statement: &str, error: OperandError) -> ParseError {
+        self.operand_error_at(statement, error, self.tokenizer.span())
+    }
+
+    fn operand_error_at(&self, statement: &str, error: OperandError, span: Span) -> ParseError {
+        match error {
+            OperandError::Syntax => ParseError::StatementSyntaxError(statement.to_owned(), span),
+            OperandError::Lex(error) => ParseError::LexError(error, span),
+            OperandError::OutOfRange {
+                value,
+                field,
+                span: operand,
+            } => ParseError::ImmediateOutOfRange {
+                value,
+                min: field.min(),
+                max: field.max(),
+                field: field.name,
+                span: operand.unwrap_or(span),
+            },
+            OperandError::Eval(EvalError::Undefined(name, span)) => {
+                ParseError::ConstantUsedBeforeDefinition(name, span)
+            }
+            OperandError::Eval(EvalError::Recursive(name, span)) => {
+                ParseError::RecursiveConstant(name, span)
+            }
+            OperandError::Eval(error) => ParseError::InvalidExpression(error),
+        }
+    }
+
+    /// skips the rest of a broken statement, so parsing can resume at the next line
+    fn synchronize(&mut self) {
+        if !self.tokenizer.at_line_start() {
+            eat_comment(&mut self.tokenizer);
+        }
+    }
+}
+
+pub fn translate(text: &str) -> Result<TranslationOutput, Vec<ParseError>> {
+    translate_with(text, 0, &Options::default())
+}
+
+/// parses a define given as `NAME` or `NAME=value`, where the value defaults to 1
+/// and is written like a number in the classic syntax, as in `-D BASE=x3000`
+pub fn parse_define(define: &str) -> Option<(String, i32)> {
+    let (name, value) = define.split_once('=').unwrap_or((define, "1"));
+    if !is_symbol(name)
+        || !name
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+    {
+        return None;
+    }
+    let tokens: Vec<_> = Tokenizer::new(value).classic(true).collect();
+    let value = match tokens.as_slice() {
+        [Ok(Token::Number(value))] => *value,
+        [Ok(Token::Minus), Ok(Token::Number(value))] => -value,
+        _ => return None,
+    };
+    Some((name.to_owned(), value))
+}
+
+/// parses the placement of a section given as `name=address`,
+/// where the address is even and written like the value of [`parse_define`]
+pub fn parse_section(section: &str) -> Option<(String, i32)> {
+    if !section.contains('=') {
+        return None;
+    }
+    parse_define(section).filter(|(_, addr)| addr % 2 == 0 && WORD.check(*addr).is_ok())
+}
+
+/// parses a named trap given as `name=vector`, written like a definition for [`parse_define`]
+pub fn parse_trap_name(trap: &str) -> Option<(String, u8)> {
+    if !trap.contains('=') {
+        return None;
+    }
+    let (name, vector) = parse_define(trap)?;
+    TRAPVECT8.check(vector).ok()?;
+    Some((name, vector as u8))
+}
+
+/// a [`Loader`] for input that cannot include files
+struct NoFiles;
+
+impl Loader for NoFiles {
+    fn load(&mut self, _path: &str, _from: FileId) -> Option<(FileId, String)> {
+        None
+    }
+
+    fn translate_once(&mut self, _file: FileId) -> bool {
+        true
+    }
+}
+
+/// translates `text`, marking all positions as belonging to `file`.
+///
+/// a broken statement does not stop the translation,
+/// instead every error up to `options.error_limit` is reported
+pub fn translate_with(
+    text: &str,
+    file: FileId,
+    options: &Options,
+) -> Result<TranslationOutput, Vec<ParseError>> {
+    translate_with_loader(text, file, options, &mut NoFiles)
+}
+
+/// translates `text` like [`translate_with`], reading the files it includes with `loader`
+pub fn translate_with_loader<'a>(
+    text: &'a str,
+    file: FileId,
+    options: &Options,
+    loader: &'a mut dyn Loader,
+) -> Result<TranslationOutput, Vec<ParseError>> {
+    let mut translator = Translator {
+        tokenizer: Tokenizer::with_file(text, file).classic(options.classic),
+        instructions: vec![],
+        sections: vec![Section::new(
+            TEXT,
+            Span {
+                file,
+                ..Span::default()
+            },
+        )],
+        section: 0,
+        labels: HashMap::new(),
+        to_resolve: vec![],
+        used: HashSet::new(),
+        current_addr: 0,
+        constants: HashMap::new(),
+        fixups: vec![],
+        warnings: vec![],
+        origin: 0,
+        origin_settable: true,
+        ended: false,
+        reachable: true,
+        falls_through: false,
+        macros: HashMap::new(),
+        traps: TRAPS
+            .iter()
+            .map(|&(name, vector)| (name.to_owned(), vector))
+            .chain(options.traps.iter().map(|(name, vector)| {
+                // in the classic syntax, trap names are looked up in uppercase
+                let name = if options.classic {
+                    name.to_ascii_uppercase()
+                } else {
+                    name.to_owned()
+                };
+                (name, *vector)
+            }))
+            .collect(),
+        classic: options.classic,
+        expansions: 0,
+        scope: String::new(),
+        conditionals: vec![],
+        predefined: HashSet::new(),
+        loader,
+        file,
+    };
+    translator.loader.translate_once(file);
+    for (name, value) in &options.defines {
+        let constant = Constant {
+            value: Expr::Number(*value),
+            span: Span {
+                file,
+                ..Span::default()
+            },
+        };
+        translator.constants.insert(name.to_owned(), constant);
+        translator.predefined.insert(name.to_owned());
+    }
+    let mut errors = vec![];
+
+    while !translator.ended {
+        let Some(next) = translator.tokenizer.next() else {
+            break;
+        };
+        let result = match next {
+            Ok(next) => translator.statement(next),
+            Err(error) => Err(ParseError::LexError(error, translator.tokenizer.span())),
+        };
+        if let Err(error) = result {
+            errors.push(translator.in_expansions(error));
+            if options.limit_reached(errors.len()) {
+                break;
+            }
+            translator.synchronize();
+        }
+    }
+
+    if !translator.ended {
+        let unterminated = translator.conditionals.iter();
+        errors.extend(
+            unterminated.map(|conditional| ParseError::UnterminatedConditional(conditional.span)),
+        );
+    }
+    translator.store_section();
+    if !options.limit_reached(errors.len()) {
+        errors.extend(translator.resolve_targets());
+        errors.extend(translator.resolve_fixups());
+        if let Some(limit) = options.error_limit {
+            errors.truncate(limit);
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    // sections of the next translation unit start with a new word
+    for section in &mut translator.sections {
+        section.size += section.size % 2;
+    }
+    Ok(TranslationOutput {
+        labels: translator.labels,
+        sections: translator.sections,
+        to_resolve: translator.to_resolve,
+        used: translator.used,
+        origin: translator.origin,
+        redefinitions: vec![],
+        warnings: translator.warnings,
+    })
+}
+
+/// computes the offset in words from the instruction at `current_addr` to `target`,
+/// written as `label`, which has to be word aligned and fit into the PCoffset `field`
+fn pc_offset(
+    addresses: &HashMap<String, i32>,
+    target: &Expr,
+    label: String,
+    current_addr: i32,
+    field: Field,
+    span: Span,
+) -> Result<i32, LinkError> {
+    let scope = Scope {
+        constants: &HashMap::new(),
+        label: &|name| addresses.get(name).copied(),
+        location: Some(current_addr),
+    };
+    let label_loc = match target.evaluate(&scope) {
+        Ok(label_loc) => label_loc,
+        Err(EvalError::Undefined(label, span)) => {
+            let suggestion =
+                closest(&label, addresses.keys().map(String::as_str)).map(str::to_owned);
+            return Err(LinkError::LabelNotResolvedError {
+                label,
+                suggestion,
+                span,
+            });
+        }
+        Err(error) => return Err(LinkError::InvalidExpression(error)),
+    };
+    let distance = label_loc - current_addr - 2;
+    if distance % 2 != 0 {
+        return Err(LinkError::UnalignedLabel { label, span });
+    }
+    let offset = distance / 2;
+    if (field.min()..=field.max()).contains(&offset) {
+        Ok(offset)
+    } else {
+        Err(LinkError::OffsetOutOfRange {
+            label,
+            distance: offset,
+            min: field.min(),
+            max: field.max(),
+            span,
+        })
+    }
+}
+
+/// evaluates an immediate depending on labels or `$`, the byte address `location`,
+/// which has to fit into the field of the fixup
+fn evaluate_fixup(
+    addresses: &HashMap<String, i32>,
+    location: i32,
+    fixup: &Fixup,
+) -> Result<i32, LinkError> {
+    let scope = Scope {
+        constants: &HashMap::new(),
+        label: &|name| addresses.get(name).copied(),
+        location: Some(location),
+    };
+    let value = match fixup.value.evaluate(&scope) {
+        Ok(value) => value,
+        Err(EvalError::Undefined(label, span)) => {
+            let suggestion =
+                closest(&label, addresses.keys().map(String::as_str)).map(str::to_owned);
+            return Err(LinkError::LabelNotResolvedError {
+                label,
+                suggestion,
+                span,
+            });
+        }
+        Err(error) => return Err(LinkError::InvalidExpression(error)),
+    };
+    let field = fixup.field;
+    if (field.min()..=field.max()).contains(&value) {
+        Ok(value)
+    } else {
+        Err(LinkError::ImmediateOutOfRange {
+            value,
+            min: field.min(),
+            max: field.max(),
+            field: field.name,
+            span: fixup.span,
+        })
     }
+}
 
-    // println!("{labels:?}");
+/// the output of [`link_with`]
+#[derive(Debug, PartialEq)]
+pub struct Image {
+    /// byte address of the first word, set by `.ORIG`
+    pub origin: i32,
+    pub words: Vec<u16>,
+}
 
-    Ok(TranslationOutput {
-        labels,
-        instructions,
-        to_resolve: branches,
-        last_address: current_addr,
-    })
+/// links with the default options, returning only the words
+pub fn link(translation: TranslationOutput) -> Result<Vec<u16>, Vec<LinkError>> {
+    link_with(translation, &Options::default()).map(|image| image.words)
 }
 
-pub fn link(mut translation: TranslationOutput) -> Result<Vec<u16>, LinkError> {
+/// resolves the labels of `translation`, reporting every unresolved one up to `options.error_limit`
+pub fn link_with(
+    mut translation: TranslationOutput,
+    options: &Options,
+) -> Result<Image, Vec<LinkError>> {
+    let mut errors = translation.redefinitions;
+
+    // place the sections
+    let mut bases = Vec::with_capacity(translation.sections.len());
+    let mut end = translation.origin;
+    for section in &translation.sections {
+        let placement = options
+            .sections
+            .iter()
+            .find(|(name, _)| *name == section.name);
+        let base = placement.map_or(end, |&(_, addr)| addr);
+        if base < end || base % 2 != 0 {
+            errors.push(LinkError::InvalidSectionAddress {
+                section: section.name.to_owned(),
+                addr: base,
+                end,
+                span: section.span,
+            });
+        }
+        bases.push(base);
+        end = base.max(end) + section.size;
+    }
+    let addresses: HashMap<String, i32> = translation
+        .labels
+        .iter()
+        .map(|(name, label)| (name.to_owned(), bases[label.section] + label.addr))
+        .collect();
+
     // resolve branches
     for load in translation.to_resolve {
-        match load {
-            AddressResolving::Branch(br) => {
-                if let Some(label_loc) = translation.labels.get(&br.label) {
-                    let offset: i32 = label_loc - br.current_addr - 1;
-                    let offset: i16 = offset.try_into().unwrap();
-                    translation.instructions[br.index] = branch(br.n, br.z, br.p, offset);
-                } else {
-                    return Err(LinkError::LabelNotResolvedError(
-                        br.label.to_owned(),
-                        br.line_number,
-                    ));
-                }
-            }
-            AddressResolving::Lea(load_effective_address) => {
-                if let Some(label_loc) = translation.labels.get(&load_effective_address.label) {
-                    let offset: i32 = label_loc - load_effective_address.current_addr - 1;
-                    let offset: i16 = offset.try_into().unwrap();
-                    translation.instructions[load_effective_address.index] =
-                        lea(load_effective_address.dest, offset);
-                } else {
-                    return Err(LinkError::LabelNotResolvedError(
-                        load_effective_address.label.to_owned(),
-                        load_effective_address.line_number,
-                    ));
-                }
+        if options.limit_reached(errors.len()) {
+            break;
+        }
+        let resolved = match load {
+            AddressResolving::Branch(br) => pc_offset(
+                &addresses,
+                &br.target,
+                br.label,
+                bases[br.section] + br.current_addr,
+                PCOFFSET9,
+                br.span,
+            )
+            .map(|offset| {
+                let instruction = branch(br.n, br.z, br.p, offset as i16);
+                (br.section, br.index, instruction)
+            }),
+            AddressResolving::Lea(load_effective_address) => pc_offset(
+                &addresses,
+                &load_effective_address.target,
+                load_effective_address.label,
+                bases[load_effective_address.section] + load_effective_address.current_addr,
+                PCOFFSET9,
+                load_effective_address.span,
+            )
+            .map(|offset| {
+                (
+                    load_effective_address.section,
+                    load_effective_address.index,
+                    lea(load_effective_address.dest, offset as i16),
+                )
+            }),
+            AddressResolving::Jsr(jump_subroutine) => pc_offset(
+                &addresses,
+                &jump_subroutine.target,
+                jump_subroutine.label,
+                bases[jump_subroutine.section] + jump_subroutine.current_addr,
+                PCOFFSET11,
+                jump_subroutine.span,
+            )
+            .map(|offset| {
+                let instruction = jsr(offset);
+                (jump_subroutine.section, jump_subroutine.index, instruction)
+            }),
+            AddressResolving::Expression(fixup) => {
+                let location = bases[fixup.section] + fixup.current_addr;
+                evaluate_fixup(&addresses, location, &fixup).map(|value| {
+                    let encoded = fixup.field.encode(value) << fixup.shift;
+                    let word = translation.sections[fixup.section].words[fixup.index];
+                    (fixup.section, fixup.index, word | encoded)
+                })
             }
-            AddressResolving::Jsr(jump_subroutine) => {
-                if let Some(label_loc) = translation.labels.get(&jump_subroutine.label) {
-                    let offset: i32 = label_loc - jump_subroutine.current_addr - 1;
-                    translation.instructions[jump_subroutine.index] = jsr(offset);
-                } else {
-                    return Err(LinkError::LabelNotResolvedError(
-                        jump_subroutine.label.to_owned(),
-                        jump_subroutine.line_number,
-                    ));
-                }
+        };
+
+        match resolved {
+            Ok((section, index, instruction)) => {
+                translation.sections[section].words[index] = instruction
             }
+            Err(error) => errors.push(error),
         }
     }
 
-    Ok(translation.instructions)
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    // put the sections together, filling the gaps and `.BSS` with zeros
+    let mut instructions = vec![];
+    for (section, base) in translation.sections.into_iter().zip(bases) {
+        instructions.resize(((base - translation.origin) / 2) as usize, 0);
+        instructions.extend(section.words);
+        instructions.resize(((base + section.size - translation.origin) / 2) as usize, 0);
+    }
+    Ok(Image {
+        origin: translation.origin,
+        words: instructions,
+    })
 }
 
 #[cfg(test)]
 mod tests {
 
-    use super::{link, translate};
+    use super::{
+        link, link_with, parse_define, parse_section, parse_trap_name, translate, translate_with,
+        translate_with_loader, LinkError, Options, ParseError, Warning,
+    };
+    use crate::parser::{include::Loader, source::FileId};
+    use std::collections::HashSet;
 
     #[allow(clippy::unusual_byte_groupings)]
     #[test]
@@ -930,4 +2991,881 @@ mod tests {
             Ok(vec![0x6968, 0x0000, 0x0000, 0x0000, 0x0000])
         );
     }
+
+    #[test]
+    fn should_report_all_errors() {
+        let text = "ADD R0, R0\nAND R0, R0, #0\nJMP #3\nlabel\nRET";
+        let errors = translate(text).expect_err("input has errors");
+        assert_eq!(errors.len(), 3);
+        assert!(
+            matches!(&errors[0], ParseError::StatementSyntaxError(name, span) if name == "ADD" && span.line == 1)
+        );
+        assert!(
+            matches!(&errors[1], ParseError::StatementSyntaxError(name, span) if name == "JMP" && span.line == 3)
+        );
+        assert!(
+            matches!(&errors[2], ParseError::LabelSyntaxError(name, span) if name == "label" && span.line == 4)
+        );
+
+        let options = Options {
+            error_limit: Some(2),
+            ..Options::default()
+        };
+        let errors = translate_with(text, 0, &options).expect_err("input has errors");
+        assert_eq!(errors.len(), 2);
+    }
+
+    #[test]
+    fn should_report_all_unresolved_labels() {
+        let text = "BR first\nLEA R0, second\nJSR third";
+        let translation = translate(text).expect("should parse input");
+        let errors = link(translation).expect_err("labels are missing");
+        let labels: Vec<_> = errors
+            .iter()
+            .map(|error| match error {
+                LinkError::LabelNotResolvedError { label, .. } => label.as_str(),
+                error => panic!("unexpected error {error:?}"),
+            })
+            .collect();
+        assert_eq!(labels, ["first", "second", "third"]);
+
+        let translation = translate(text).expect("should parse input");
+        let options = Options {
+            error_limit: Some(1),
+            ..Options::default()
+        };
+        assert_eq!(link_with(translation, &options).unwrap_err().len(), 1);
+    }
+
+    #[test]
+    fn should_check_immediate_ranges() {
+        let out_of_range = |text: &str| match translate(text) {
+            Err(errors) => match &errors[..] {
+                [ParseError::ImmediateOutOfRange {
+                    value,
+                    min,
+                    max,
+                    field,
+                    ..
+                }] => Some((*value, *min, *max, *field)),
+                errors => panic!("unexpected errors {errors:?}"),
+            },
+            Ok(_) => None,
+        };
+        assert_eq!(
+            out_of_range("ADD R0, R0, #100"),
+            Some((100, -16, 15, "imm5"))
+        );
+        assert_eq!(
+            out_of_range("AND R0, R0, #-17"),
+            Some((-17, -16, 15, "imm5"))
+        );
+        assert_eq!(out_of_range("XOR R0, R0, #-16"), None);
+        assert_eq!(
+            out_of_range("LSHF R0, R0, #16"),
+            Some((16, 0, 15, "amount4"))
+        );
+        assert_eq!(out_of_range("TRAP x100"), Some((256, 0, 255, "trapvect8")));
+        assert_eq!(
+            out_of_range("LDB R0, R1, #-33"),
+            Some((-33, -32, 31, "offset6"))
+        );
+        assert_eq!(
+            out_of_range("STW R0, R1, #32"),
+            Some((32, -32, 31, "offset6"))
+        );
+        assert_eq!(out_of_range("DB #1, #256"), Some((256, 0, 255, "byte")));
+        assert_eq!(
+            out_of_range("DW x10000"),
+            Some((0x10000, 0, 0xffff, "word"))
+        );
+
+        // the whole expression is underlined
+        let errors =
+            translate("SIZE = #16\nADD R0, R0, SIZE - #1 + #16").expect_err("out of range");
+        let span = errors[0].span();
+        assert_eq!((span.line, span.column, span.end - span.start), (2, 13, 15));
+    }
+
+    #[allow(clippy::unusual_byte_groupings)]
+    #[test]
+    fn should_encode_negative_offsets() {
+        let text = "LDB R1, R4, #-1\nADD R0, R0, #-16";
+        let translation = translate(text).expect("should parse input");
+        assert_eq!(
+            link(translation),
+            Ok(vec![0b0010_001_100_111111, 0b0001_000_000_1_10000])
+        );
+    }
+
+    #[allow(clippy::unusual_byte_groupings)]
+    #[test]
+    fn should_encode_numeric_pc_offsets() {
+        let translation = translate("BRnzp #-1\nLEA R0, #3\nJSR x10").expect("should parse input");
+        assert_eq!(
+            link(translation),
+            Ok(vec![
+                0b0000_111_111111111,
+                0b1110_000_000000011,
+                0b0100_1_00000010000
+            ])
+        );
+
+        let errors = translate("BRz #256\nJSR #-1025").expect_err("offsets are out of range");
+        let errors: Vec<_> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            errors,
+            [
+                "256 does not fit into PCoffset9, expected a value from -256 to 255",
+                "-1025 does not fit into PCoffset11, expected a value from -1024 to 1023",
+            ]
+        );
+    }
+
+    #[test]
+    fn should_check_offset_ranges() {
+        let text = "BR far\n. = x200\nfar:";
+        let translation = translate(text).expect("should parse input");
+        assert_eq!(link(translation), Ok([&[0x0eff], &[0; 0xff][..]].concat()));
+
+        let text = "LEA R0, far\nJSR far\n. = x202\nfar:\nBRz back\n. = x802\nback:";
+        let translation = translate(text).expect("should parse input");
+        let errors = link(translation).expect_err("labels are out of reach");
+        let errors: Vec<_> = errors
+            .iter()
+            .map(|error| match error {
+                LinkError::OffsetOutOfRange {
+                    label,
+                    distance,
+                    min,
+                    max,
+                    ..
+                } => (label.as_str(), *distance, *min, *max),
+                error => panic!("unexpected error {error:?}"),
+            })
+            .collect();
+        assert_eq!(errors, [("far", 256, -256, 255), ("back", 767, -256, 255)]);
+
+        let text = "back:\n. = x800\nJSR back";
+        let translation = translate(text).expect("should parse input");
+        let errors = link(translation).expect_err("label is out of reach");
+        assert!(matches!(
+            &errors[..],
+            [LinkError::OffsetOutOfRange {
+                distance: -1025,
+                min: -1024,
+                max: 1023,
+                ..
+            }]
+        ));
+    }
+
+    #[allow(clippy::unusual_byte_groupings)]
+    #[test]
+    fn should_resolve_target_expressions() {
+        let text = ".ORIG x3000\nloop: ADD R0, R0, #1\nBR loop + #2\nLEA R0, table + #4\nENTRY = x3000\nLEA R1, ENTRY\nJSR LATER\nLATER = ENTRY + #2\ntable: DW #0, #0, #0";
+        let translation = translate(text).expect("should parse input");
+        assert_eq!(
+            link(translation),
+            Ok(vec![
+                0b0001_000_000_1_00001,
+                0b0000_111_111111111,
+                0b1110_000_000000100,
+                0b1110_001_111111100,
+                0b0100_1_11111111100,
+                0,
+                0,
+                0
+            ])
+        );
+
+        let constant = ".ORIG x3000\nTARGET = x3004\nBRz TARGET\nHALT\nlabel: HALT";
+        let label = ".ORIG x3000\nBRz label\nHALT\nlabel: HALT";
+        let constant = link(translate(constant).expect("should parse input"));
+        let label = link(translate(label).expect("should parse input"));
+        assert_eq!(constant, Ok(vec![0b0000_010_000000001, 0xf025, 0xf025]));
+        assert_eq!(constant, label);
+
+        let text = "BRz FAR\nFAR = x202";
+        let errors = link(translate(text).expect("should parse input")).expect_err("out of reach");
+        assert!(matches!(
+            &errors[..],
+            [LinkError::OffsetOutOfRange { label, distance: 256, .. }] if label == "FAR"
+        ));
+
+        let text = "BR far + #2\n. = x200\nfar:";
+        let errors = link(translate(text).expect("should parse input")).expect_err("out of reach");
+        assert!(matches!(
+            &errors[..],
+            [LinkError::OffsetOutOfRange { label, distance: 256, .. }] if label == "far + #2"
+        ));
+    }
+
+    #[test]
+    fn should_report_lex_errors() {
+        let text = "DB \"unclosed\nADD R0, R0, #1\nDB \"\\xg\", #0\n; it's a \"comment\nRET";
+        let errors = translate(text).expect_err("input has errors");
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| (error.to_string(), error.span().line))
+                .collect::<Vec<_>>(),
+            [
+                ("unclosed string".to_owned(), 1),
+                ("\\x expects 2 hex digits".to_owned(), 3)
+            ]
+        );
+    }
+
+    #[test]
+    fn should_reject_duplicate_labels() {
+        let errors = translate("loop:\nRET\nloop:\nRET").expect_err("loop is defined twice");
+        assert!(matches!(
+            &errors[..],
+            [ParseError::DuplicateLabel { label, first, second }]
+                if label == "loop" && first.line == 1 && second.line == 3
+        ));
+
+        let mut translation = translate("loop:\nRET").expect("should parse input");
+        translation.extend(translate("RET\nloop:").expect("should parse input"));
+        let errors = link(translation).expect_err("loop is defined twice");
+        assert!(matches!(
+            &errors[..],
+            [LinkError::DuplicateLabel { label, first, second }]
+                if label == "loop" && first.line == 1 && second.line == 2
+        ));
+    }
+
+    #[test]
+    fn should_warn() {
+        let text = "start:\nHALT\nADD R0, R0, R0\nloop:\nBR loop\nunused:\nADD R0, R0, R0\nDW x1234\n. = x1000\n";
+        let translation = translate(text).expect("should parse input");
+        let warnings: Vec<_> = translation
+            .warnings()
+            .iter()
+            .map(|warning| (warning.kind().name(), warning.span().line))
+            .collect();
+        assert_eq!(
+            warnings,
+            [
+                ("unreachable-code", 3),
+                ("fall-into-data", 8),
+                ("location-gap", 9),
+                ("unused-label", 1),
+                ("unused-label", 6),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_count_all_label_uses() {
+        let unused = |text: &str| -> Vec<_> {
+            let translation = translate(text).expect("should parse input");
+            translation
+                .warnings()
+                .into_iter()
+                .filter_map(|warning| match warning {
+                    Warning::UnusedLabel(label, span) => Some((label, span.line)),
+                    _ => None,
+                })
+                .collect()
+        };
+        let text = "start: HALT\nend:\n.BLKW end - start\nflag: HALT\n.IFDEF flag\n.ENDIF\n.IF start\n.ENDIF\nlonely: HALT";
+        assert_eq!(unused(text), [("lonely".to_owned(), 9)]);
+
+        let text = ".MACRO WAIT\nagain: BRz again\nunused:\n.ENDM\nWAIT\nWAIT\nHALT";
+        assert_eq!(unused(text), []);
+    }
+
+    #[test]
+    fn should_not_warn_after_labels() {
+        let text = "loop:\nBRz done\nBR loop\ndone:\nRET\ndata:\nDB \"hi\"";
+        let translation = translate(text).expect("should parse input");
+        assert_eq!(
+            translation.warnings(),
+            [Warning::UnusedLabel(
+                "data".to_owned(),
+                translation.labels["data"].span
+            )]
+        );
+    }
+
+    #[test]
+    fn should_suggest_mnemonics() {
+        let text = "LDR R0, R1, #0\nADDD R0, R0, R0\nFOO R1\nHALTT\nlabel ; no colon";
+        let errors = translate(text).expect_err("input has errors");
+        let errors: Vec<_> = errors
+            .iter()
+            .map(|error| (error.to_string(), error.help()))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                (
+                    "unknown mnemonic \"LDR\"".to_owned(),
+                    Some("did you mean \"LDB\"?".to_owned())
+                ),
+                (
+                    "unknown mnemonic \"ADDD\"".to_owned(),
+                    Some("did you mean \"ADD\"?".to_owned())
+                ),
+                ("unknown mnemonic \"FOO\"".to_owned(), None),
+                (
+                    "unknown mnemonic \"HALTT\"".to_owned(),
+                    Some("did you mean \"HALT\"?".to_owned())
+                ),
+                (
+                    "syntax error while parsing label \"label\"".to_owned(),
+                    Some("labels are defined with a trailing colon, as in \"label:\"".to_owned())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_suggest_labels() {
+        let text = "loop:\nBR lopo\nBR Loop\nBR elsewhere";
+        let translation = translate(text).expect("should parse input");
+        let errors = link(translation).expect_err("labels are misspelled");
+        let suggestions: Vec<_> = errors
+            .iter()
+            .map(|error| match error {
+                LinkError::LabelNotResolvedError { suggestion, .. } => suggestion.as_deref(),
+                error => panic!("unexpected error {error:?}"),
+            })
+            .collect();
+        assert_eq!(suggestions, [Some("loop"), Some("loop"), None]);
+    }
+
+    #[allow(clippy::unusual_byte_groupings)]
+    #[test]
+    fn should_translate_classic_syntax() {
+        let text = "loop: add r0, r0, -1\nbrpz loop";
+        let options = Options {
+            classic: true,
+            ..Options::default()
+        };
+        let translation = translate_with(text, 0, &options).expect("should parse input");
+        assert_eq!(
+            link(translation),
+            Ok(vec![0b0001_000_000_1_11111, 0b0000_0_1_1_111111110])
+        );
+        assert!(translate(text).is_err());
+    }
+
+    #[test]
+    fn should_translate_directives() {
+        let text = "START = x3000\n.ORIG START\nLEA R0, msg\nPUTS\nHALT\nmsg: .STRINGZ \"hi\"\n.BLKW #2\n.FILL #-1\n.FILL xbeef\n. = x3018\n.FILL #1\n.END\nthis is ignored \"";
+        let translation = translate(text).expect("should parse input");
+        assert!(translation.warnings().is_empty());
+        assert_eq!(
+            link(translation),
+            Ok(vec![
+                0xe002, 0xf022, 0xf025, 0x0068, 0x0069, 0x0000, 0x0000, 0x0000, 0xffff, 0xbeef,
+                0x0000, 0x0000, 0x0001
+            ])
+        );
+
+        let errors = translate("ADD R0, R0, R0\n.ORIG x3000\n.ORIG x3001\n.FOO\n.FILL x10000")
+            .expect_err("input has errors");
+        let errors: Vec<_> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            errors,
+            [
+                "cannot set location",
+                "cannot set location",
+                "unknown mnemonic \".FOO\"",
+                "65536 does not fit into word, expected a value from -32768 to 65535",
+            ]
+        );
+    }
+
+    #[allow(clippy::unusual_byte_groupings)]
+    #[test]
+    fn should_resolve_constants() {
+        let text = "ADD R0, R0, STEP\nLSHF R1, R1, BITS\nLDW R2, R6, FRAME\nTRAP HALT_VECTOR\nDB #1, CHAR, CHAR\n.ALIGN #2\nDW BIG\n.FILL STEP\n.EQU STEP, #-2\nBITS = #4\n.EQU FRAME FRAME_SIZE\nFRAME_SIZE = #3\nHALT_VECTOR = x25\nCHAR = x41\nBIG = xbeef\nGAP = x1c\n. = GAP\n";
+        let translation = translate(text).expect("should parse input");
+        assert_eq!(
+            link(translation),
+            Ok(vec![
+                0b0001_000_000_1_11110,
+                0b1101_001_001_00_0100,
+                0b0110_010_110_000011,
+                0xf025,
+                0x4101,
+                0x0041,
+                0xbeef,
+                0xfffe,
+                0x0000,
+                0x0000,
+                0x0000,
+                0x0000,
+                0x0000,
+                0x0000,
+            ])
+        );
+
+        let text = "ADD R0, R0, BIG\nBIG = #16\nTRAP MISSING\nA = B\nB = A\nDW A\n. = LATER\nLATER = #2\nBIG = #1\nBIG:";
+        let errors = translate(text).expect_err("input has errors");
+        let errors: Vec<_> = errors
+            .iter()
+            .map(|error| (error.to_string(), error.span().line))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                (
+                    "\"LATER\" has to be defined before it is used here".to_owned(),
+                    7
+                ),
+                ("constant \"BIG\" is defined multiple times".to_owned(), 9),
+                ("label \"BIG\" is defined multiple times".to_owned(), 10),
+                (
+                    "16 does not fit into imm5, expected a value from -16 to 15".to_owned(),
+                    1
+                ),
+                ("constant \"A\" is defined in terms of itself".to_owned(), 5),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_evaluate_expressions() {
+        let text = ".ORIG x3000\nSIZE = #16\nstart: ADD R0, R0, SIZE - #1 - #16\nHALT\nDW (x10 << #2) | #1\nDW table + #4\nDW end - start\nDW $\ntable: DW #0\nend:";
+        let translation = translate(text).expect("should parse input");
+        assert!(translation.warnings().is_empty());
+        assert_eq!(
+            link(translation),
+            Ok(vec![0x103f, 0xf025, 0x0041, 0x3010, 0x000e, 0x300a, 0x0000])
+        );
+
+        let errors = translate("ADD R0, R0, #1 / #0\nDW -(#1").expect_err("input has errors");
+        let errors: Vec<_> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            errors,
+            ["division by zero", "syntax error while parsing \"DW\""]
+        );
+
+        let text = "start: ADD R0, R0, end - start\nTRAP MISSING\nDW $ % #0\nDW #0, #0, #0, #0, #0, #0, #0\nend:";
+        let errors = link(translate(text).expect("should parse input")).expect_err("should fail");
+        let errors: Vec<_> = errors
+            .iter()
+            .map(|error| (error.to_string(), error.span().line))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                (
+                    "20 does not fit into imm5, expected a value from -16 to 15".to_owned(),
+                    1
+                ),
+                (
+                    "could not resolve label \"MISSING\" during link".to_owned(),
+                    2
+                ),
+                ("division by zero".to_owned(), 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_emit_label_addresses() {
+        let mut translation =
+            translate(".ORIG x3000\nLDW R0, R0, #0\nHALT\ntable: DW handler_a, handler_b, msg+#2")
+                .expect("should parse input");
+        let text = "handler_a: RET\nhandler_b: RET\nmsg: DB \"hi!\", #0\nDW $, table";
+        translation.extend(translate(text).expect("should parse input"));
+        assert_eq!(
+            link(translation),
+            Ok(vec![
+                0x6000, 0xf025, 0x300a, 0x300c, 0x3010, 0xc1c0, 0xc1c0, 0x6968, 0x0021, 0x3012,
+                0x3004
+            ])
+        );
+    }
+
+    #[test]
+    fn should_expand_macros() {
+        let text = ".MACRO SAVE reg\nADD R6, R6, #-2\nSTW reg, R6, #0 ; keep reg\n.ENDM\n.macro SKIP_IF_ZERO reg, target\nADD reg, reg, #0\nBRz skip\nBR target\nskip:\n.endm\nSAVE R7\nSKIP_IF_ZERO R1, done\nSKIP_IF_ZERO R2, done\ndone: HALT";
+        let translation = translate(text).expect("should parse input");
+        assert_eq!(
+            link(translation),
+            Ok(vec![
+                0x1dbe, 0x7f80, 0x1260, 0x0401, 0x0e03, 0x14a0, 0x0401, 0x0e00, 0xf025
+            ])
+        );
+
+        let text = ".MACRO LOAD reg, value\nADD reg, reg, value\n.ENDM\nLOAD R0, #1\nLOAD R0, #100\nLOAD R0\n.MACRO AGAIN\nAGAIN\n.ENDM\nAGAIN\n.MACRO LOAD\n.ENDM\n.MACRO BROKEN\nRET";
+        let errors = translate(text).expect_err("input has errors");
+        let errors: Vec<_> = errors
+            .iter()
+            .map(|error| match error {
+                ParseError::InMacro { error, call, .. } => {
+                    (error.to_string(), error.span().line, Some(call.line))
+                }
+                error => (error.to_string(), error.span().line, None),
+            })
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                (
+                    "100 does not fit into imm5, expected a value from -16 to 15".to_owned(),
+                    2,
+                    Some(5)
+                ),
+                (
+                    "macro \"LOAD\" takes 2 arguments, but 1 were given".to_owned(),
+                    6,
+                    None
+                ),
+                ("macro \"AGAIN\" expands itself".to_owned(), 8, Some(10)),
+                (
+                    "macro \"LOAD\" is defined multiple times".to_owned(),
+                    11,
+                    None
+                ),
+                ("macro \"BROKEN\" is missing .ENDM".to_owned(), 13, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_translate_conditionals() {
+        let text = "DEBUG = #1\n.IF DEBUG\nTRAP x21\n.ELSE\nTRAP x22 \"unclosed\n.ENDIF\n.IFNDEF RELEASE\n.IFDEF DEBUG\nTRAP x23\n.ENDIF\n.ELSE\nTRAP x20\n.ENDIF\n.IF DEBUG - #1\n.IF #1\n\"nested\n.ENDIF\nTRAP x24\n.endif\nHALT";
+        let translation = translate(text).expect("should parse input");
+        assert_eq!(link(translation), Ok(vec![0xf021, 0xf023, 0xf025]));
+
+        let text = "DEBUG = #1\n.IF DEBUG\nTRAP x21\n.ELSE\nTRAP x22\n.ENDIF\n.IFDEF RELEASE\nTRAP x20\n.ENDIF";
+        let options = Options {
+            defines: vec![("DEBUG".to_owned(), 0), ("RELEASE".to_owned(), 1)],
+            ..Options::default()
+        };
+        let translation = translate_with(text, 0, &options).expect("should parse input");
+        assert_eq!(link(translation), Ok(vec![0xf022, 0xf020]));
+
+        let text = ".ELSE\n.IF #1\n.ELSE\n.ELSE\n.ENDIF\n.ENDIF\n.IFDEF X\n";
+        let errors = translate(text).expect_err("input has errors");
+        let errors: Vec<_> = errors
+            .iter()
+            .map(|error| (error.to_string(), error.span().line))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                (".ELSE without a matching .IF".to_owned(), 1),
+                (".ENDIF without a matching .IF".to_owned(), 6),
+                (".IF is missing .ENDIF".to_owned(), 7),
+            ]
+        );
+
+        let text = ".IF MISSING + \nTRAP x21\n.ELSE\nTRAP x22\n.ENDIF";
+        let errors = translate(text).expect_err("condition is broken");
+        let errors: Vec<_> = errors
+            .iter()
+            .map(|error| (error.to_string(), error.span().line))
+            .collect();
+        assert_eq!(
+            errors,
+            [("syntax error while parsing \".IF\"".to_owned(), 1)]
+        );
+    }
+
+    #[test]
+    fn should_parse_defines() {
+        assert_eq!(parse_define("DEBUG"), Some(("DEBUG".to_owned(), 1)));
+        assert_eq!(
+            parse_define("BASE=x3000"),
+            Some(("BASE".to_owned(), 0x3000))
+        );
+        assert_eq!(parse_define("LEVEL=-5"), Some(("LEVEL".to_owned(), -5)));
+        assert_eq!(parse_define("1ST=2"), None);
+        assert_eq!(parse_define("EMPTY="), None);
+    }
+
+    #[test]
+    fn should_scope_local_labels() {
+        let text = "print: LDB R1, R0, #0\n@loop: BRz @done\nOUT\nBR @loop\n@done: RET\nstrlen: AND R2, R2, #0\n@loop: ADD R2, R2, #1\nBR @loop\nDW print@loop";
+        let mut translation = translate(text).expect("should parse input");
+        let text = "main: JSR print\n@loop: BR print@loop";
+        translation.extend(translate(text).expect("should parse input"));
+        assert_eq!(
+            link(translation),
+            Ok(vec![
+                0x2200, 0x0402, 0xf021, 0x0ffd, 0xc1c0, 0x54a0, 0x14a1, 0x0ffe, 0x0002, 0x4ff6,
+                0x0ff6
+            ])
+        );
+
+        let errors = link(translate("first: BR @missing").unwrap()).expect_err("label is missing");
+        assert_eq!(
+            errors[0].to_string(),
+            "could not resolve label \"first@missing\" during link"
+        );
+    }
+
+    #[test]
+    fn should_expand_pseudo_instructions() {
+        let text =
+            "PUSH R7\nLDI16 R0, x1234\nSUB R1, R2, R3\nBR end\nNOP\nend: POP R7\nRET\nDW end";
+        assert_eq!(
+            link(translate(text).expect("should parse input")),
+            Ok(vec![
+                0x1dbe, 0x7f80, 0x5020, 0x1021, 0xd004, 0x1022, 0xd004, 0x1023, 0xd004, 0x1024,
+                0x92bf, 0x1243, 0x927f, 0x0e01, 0x0000, 0x6f80, 0x1da2, 0xc1c0, 0x001e
+            ])
+        );
+        let text = "MOV R1, R2\nCLR R3\nINC R4\nDEC R5\nNEG R0, R1\nLDI16 R2, #-3";
+        assert_eq!(
+            link(translate(text).expect("should parse input")),
+            Ok(vec![
+                0x12a0, 0x56e0, 0x1921, 0x1b7f, 0x907f, 0x1021, 0x54a0, 0x14bd
+            ])
+        );
+
+        let errors = translate("LDI16 R0, later\nlater: HALT").expect_err("value is not known yet");
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn should_translate_characters() {
+        let text = "ADD R0, R0, '\\n'\nDBZ \"hi\"\nDBZ \"abc\", '!'\nDB 'x', \"\\xff\"\n.STRINGZ \"\\xe9\"";
+        assert_eq!(
+            link(translate(text).expect("should parse input")),
+            Ok(vec![
+                0x102a, 0x6968, 0x6100, 0x6362, 0x0021, 0xff78, 0x00e9, 0x0000
+            ])
+        );
+        let errors = translate("ADD R0, R0, 'ab'").expect_err("literal is too long");
+        assert_eq!(errors[0].to_string(), "expected exactly one character");
+    }
+
+    #[test]
+    fn should_address_bytes() {
+        let text = ".ORIG x3000\nLEA R0, msg\nLDB R1, R0, #0\nHALT\nmsg: DB \"abc\"\nlast: DB '!'\nDW last\nDB #1\n.ALIGN #4\nDW $";
+        assert_eq!(
+            link(translate(text).expect("should parse input")),
+            Ok(vec![
+                0xe002, 0x2200, 0xf025, 0x6261, 0x2163, 0x3009, 0x0001, 0x0000, 0x3010
+            ])
+        );
+
+        let mut translation = translate("DB #1").unwrap();
+        translation.extend(translate("next: DW next").unwrap());
+        assert_eq!(link(translation), Ok(vec![0x0001, 0x0002]));
+
+        let errors = translate("DB #1\nADD R0, R0, #0\n.FILL #0").expect_err("words are odd");
+        assert_eq!(errors.len(), 2);
+        assert!(errors
+            .iter()
+            .all(|error| matches!(error, ParseError::OddAddress(_))));
+        let errors =
+            link(translate("BR odd\nDB #1\nodd: DB #2").unwrap()).expect_err("label is odd");
+        assert_eq!(errors[0].to_string(), "label \"odd\" is at an odd address");
+    }
+
+    #[test]
+    fn should_place_sections() {
+        let text = ".ORIG x3000\n.DATA\nmsg: DBZ \"hey\"\n.BSS\nbuffer: .BLKW #2\n.TEXT\nLEA R0, msg\nPUTS\nLEA R1, buffer\nHALT\n.DATA\ntable: DW msg, buffer, end\n.BSS\nend: .BLKW #1";
+        assert_eq!(
+            link(translate(text).expect("should parse input")),
+            Ok(vec![
+                0xe003, 0xf022, 0xe206, 0xf025, 0x6568, 0x0079, 0x3008, 0x3012, 0x3016, 0, 0, 0
+            ])
+        );
+
+        let options = Options {
+            sections: vec![parse_section("data=x3100").unwrap()],
+            ..Options::default()
+        };
+        let image = link_with(translate(text).unwrap(), &options).expect("should link");
+        assert_eq!((image.origin, image.words.len()), (0x3000, 136));
+        assert_eq!((image.words[0], image.words[128]), (0xe07f, 0x6568));
+        let options = Options {
+            sections: vec![("data".to_owned(), 0x3002)],
+            ..Options::default()
+        };
+        let errors = link_with(translate(text).unwrap(), &options).expect_err("data overlaps");
+        assert!(matches!(
+            &errors[..],
+            [LinkError::InvalidSectionAddress {
+                addr: 0x3002,
+                end: 0x3008,
+                ..
+            }]
+        ));
+        assert_eq!(parse_section("data=x3001"), None);
+        assert_eq!(parse_section("data"), None);
+
+        let errors = translate(".BSS\nHALT\n.FILL #1\n.DATA\n. = x10").expect_err("bss is empty");
+        assert!(matches!(
+            &errors[..],
+            [
+                ParseError::DataInBss(_),
+                ParseError::DataInBss(_),
+                ParseError::InvalidLocation { .. }
+            ]
+        ));
+
+        // the data section follows the text section padded to an even size
+        let text = ".ORIG x3000\nLEA R0, value\nDB #1\n.DATA\n.ALIGN #2\nvalue: DW #5";
+        let translation = translate(text).expect("should parse input");
+        assert_eq!(link(translation), Ok(vec![0xe001, 0x0001, 0x0005]));
+        let errors =
+            translate(".ORIG x3000\nHALT\nDB #1\n.DATA\n.ALIGN #4\nDW #5\n.BSS\n.ALIGN #8")
+                .expect_err("data cannot be aligned to more than words");
+        let errors: Vec<_> = errors
+            .iter()
+            .map(|error| (error.to_string(), error.span().line))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                ("cannot align to 4 bytes outside .TEXT".to_owned(), 5),
+                ("cannot align to 8 bytes outside .TEXT".to_owned(), 8),
+            ]
+        );
+
+        let mut translation = translate(".DATA\nDW #1\n.TEXT\nHALT").unwrap();
+        translation.extend(translate("RET\n.DATA\nx: DW x").unwrap());
+        assert_eq!(link(translation), Ok(vec![0xf025, 0xc1c0, 0x0001, 0x0006]));
+    }
+
+    #[test]
+    fn should_define_traps() {
+        let text =
+            ".TRAPDEF SQRT, x30\nVECTOR = x31\n.TRAPDEF PUTSP, VECTOR\nSQRT\nPUTSP\nGETC\nHALT";
+        let translation = translate(text).expect("should parse input");
+        assert_eq!(link(translation), Ok(vec![0xf030, 0xf031, 0xf020, 0xf025]));
+
+        let options = Options {
+            traps: vec![parse_trap_name("PRINT=x24").unwrap()],
+            classic: true,
+            ..Options::default()
+        };
+        let text = ".TRAPDEF PRINT, x24\nPRINT\nhalt\nPRINT: DW PRINT";
+        let translation = translate_with(text, 0, &options).expect("should parse input");
+        assert_eq!(link(translation), Ok(vec![0xf024, 0xf025, 0x0004]));
+        let options = Options {
+            traps: vec![parse_trap_name("Print=x24").unwrap()],
+            classic: true,
+            ..Options::default()
+        };
+        let text = ".TRAPDEF SQRT, x30\nsqrt\nSqrt\nprint\nhalt";
+        let translation = translate_with(text, 0, &options).expect("should parse input");
+        assert_eq!(link(translation), Ok(vec![0xf030, 0xf030, 0xf024, 0xf025]));
+        assert_eq!(parse_trap_name("PRINT=x100"), None);
+        assert_eq!(parse_trap_name("PRINT"), None);
+
+        let errors = translate(".TRAPDEF HALT, x30\nSQRT\n.TRAPDEF SQRT, LATER\nLATER = x30")
+            .expect_err("input has errors");
+        let errors: Vec<_> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            errors,
+            [
+                "trap \"HALT\" is already defined as x25",
+                "syntax error while parsing label \"SQRT\"",
+                "\"LATER\" has to be defined before it is used here",
+            ]
+        );
+    }
+
+    #[test]
+    fn should_define_structs() {
+        let text = ".STRUCT node\nnext: DW\nflags: DB\nname: DB #3\npairs: DW #2 ; two words\n.ENDS\nLDW R1, R0, node.next >> #1\nLDB R2, R0, node.flags\nLDB R3, R0, node.name + #1\nLDW R4, R0, node.pairs >> #1\nSTW R1, R0, (node.pairs + #2) >> #1\nADD R6, R6, node.size";
+        let translation = translate(text).expect("should parse input");
+        assert_eq!(
+            link(translation),
+            Ok(vec![0x6200, 0x2402, 0x2604, 0x6803, 0x7204, 0x1daa])
+        );
+
+        // the offsets are the byte distances of the fields in a record laid out with DB and DW
+        let text = ".STRUCT rec\ntag: DB\npad: DB\ncount: DW\n.ENDS\nLEA R0, record\nLDB R1, R0, rec.tag\nLDW R2, R0, rec.count >> #1\nHALT\nrecord: DB #7, #0\nDW x1234\nDW record + rec.count";
+        let translation = translate(text).expect("should parse input");
+        assert_eq!(
+            link(translation),
+            Ok(vec![0xe003, 0x2200, 0x6401, 0xf025, 0x0007, 0x1234, 0x000a])
+        );
+
+        let text = ".STRUCT bad\nflag: DB\nword: DW\nx DW\n.ENDS\nDW bad.size\n.STRUCT open\n";
+        let errors = translate(text).expect_err("input has errors");
+        let errors: Vec<_> = errors
+            .iter()
+            .map(|error| (error.to_string(), error.span().line))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                ("word field \"bad.word\" is at an odd offset".to_owned(), 3),
+                ("struct \"open\" is missing .ENDS".to_owned(), 7),
+            ]
+        );
+    }
+
+    /// a [`Loader`] for the files in `files`, with ids counting from 1
+    struct Files(&'static [(&'static str, &'static str)], HashSet<FileId>);
+
+    impl Loader for Files {
+        fn load(&mut self, path: &str, _from: FileId) -> Option<(FileId, String)> {
+            let index = self.0.iter().position(|(name, _)| *name == path)?;
+            Some((index + 1, self.0[index].1.to_owned()))
+        }
+
+        fn translate_once(&mut self, file: FileId) -> bool {
+            self.1.insert(file)
+        }
+    }
+
+    #[test]
+    fn should_include_files() {
+        let mut files = Files(
+            &[
+                ("io.asm", ".INCLUDE \"vectors.asm\"\nTRAP OUT_VECTOR"),
+                ("vectors.asm", "OUT_VECTOR = x21"),
+                ("a.asm", ".include \"b.asm\"\n"),
+                ("b.asm", "RET\n.INCLUDE \"a.asm\"\n"),
+            ],
+            HashSet::new(),
+        );
+        let text = "TRAP x20\n.INCLUDE \"io.asm\"\n.INCLUDE \"io.asm\"\nHALT";
+        let translation = translate_with_loader(text, 0, &Options::default(), &mut files)
+            .expect("should parse input");
+        assert_eq!(link(translation), Ok(vec![0xf020, 0xf021, 0xf025]));
+
+        let text = ".INCLUDE \"a.asm\"\n.INCLUDE \"missing.asm\"";
+        let errors = translate_with_loader(text, 0, &Options::default(), &mut files)
+            .expect_err("input has errors");
+        let errors: Vec<_> = errors
+            .iter()
+            .map(|error| {
+                let span = error.span();
+                (error.to_string(), span.file, span.line, span.column)
+            })
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                ("\"a.asm\" includes itself".to_owned(), 4, 2, 10),
+                (
+                    "cannot find \"missing.asm\" to include".to_owned(),
+                    0,
+                    2,
+                    10
+                ),
+            ]
+        );
+
+        // a file translated on its own is not included again by another translation unit
+        let mut files = Files(
+            &[("io.asm", "OUT_VECTOR = x21\nputc: TRAP OUT_VECTOR")],
+            HashSet::new(),
+        );
+        let io = translate_with_loader(files.0[0].1, 1, &Options::default(), &mut files)
+            .expect("should parse input");
+        let text = ".INCLUDE \"io.asm\"\nJSR putc";
+        let mut translation = translate_with_loader(text, 0, &Options::default(), &mut files)
+            .expect("should parse input");
+        translation.extend(io);
+        assert_eq!(link(translation), Ok(vec![0x4800, 0xf021]));
+    }
 }
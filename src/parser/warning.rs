@@ -0,0 +1,184 @@
+use std::{collections::HashSet, fmt};
+
+use super::{
+    diagnostic::{Diagnostic, Severity},
+    source::Span,
+};
+
+/// `. =` skipping more words than this is reported as [`Warning::LocationGap`]
+pub const LARGE_GAP: i32 = 0x100;
+
+/// code that assembles, but likely does not do what was intended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    /// a label no instruction refers to
+    UnusedLabel(String, Span),
+    /// an instruction after `HALT`, `RET`, `JMP` or `BR` without a label in between
+    UnreachableCode(Span),
+    /// data directly following an instruction that does not jump away
+    FallIntoData(Span),
+    /// `. =` skipping over more than [`LARGE_GAP`] words
+    LocationGap { words: i32, span: Span },
+}
+
+impl Warning {
+    pub fn kind(&self) -> WarningKind {
+        match self {
+            Warning::UnusedLabel(_, _) => WarningKind::UnusedLabel,
+            Warning::UnreachableCode(_) => WarningKind::UnreachableCode,
+            Warning::FallIntoData(_) => WarningKind::FallIntoData,
+            Warning::LocationGap { .. } => WarningKind::LocationGap,
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            Warning::UnusedLabel(_, span)
+            | Warning::UnreachableCode(span)
+            | Warning::FallIntoData(span)
+            | Warning::LocationGap { span, .. } => *span,
+        }
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::UnusedLabel(label, _) => write!(f, "label {label:?} is never used"),
+            Warning::UnreachableCode(_) => write!(f, "unreachable instruction"),
+            Warning::FallIntoData(_) => write!(f, "execution falls through into data"),
+            Warning::LocationGap { words, .. } => {
+                write!(f, "setting the location fills {words} words with zeros")
+            }
+        }
+    }
+}
+
+/// the kinds of [`Warning`]s, which can be switched on and off individually
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WarningKind {
+    UnusedLabel,
+    UnreachableCode,
+    FallIntoData,
+    LocationGap,
+}
+
+impl WarningKind {
+    pub const ALL: [WarningKind; 4] = [
+        WarningKind::UnusedLabel,
+        WarningKind::UnreachableCode,
+        WarningKind::FallIntoData,
+        WarningKind::LocationGap,
+    ];
+
+    /// name used on the command line, as in `-Wunused-label`
+    pub fn name(&self) -> &'static str {
+        match self {
+            WarningKind::UnusedLabel => "unused-label",
+            WarningKind::UnreachableCode => "unreachable-code",
+            WarningKind::FallIntoData => "fall-into-data",
+            WarningKind::LocationGap => "location-gap",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+}
+
+/// which warnings are reported, and which of them count as errors
+#[derive(Debug, Clone)]
+pub struct WarningConfig {
+    enabled: HashSet<WarningKind>,
+    errors: HashSet<WarningKind>,
+    all_errors: bool,
+}
+
+impl Default for WarningConfig {
+    fn default() -> Self {
+        Self {
+            // most programs have an entry label nobody jumps to
+            enabled: WarningKind::ALL
+                .into_iter()
+                .filter(|&kind| kind != WarningKind::UnusedLabel)
+                .collect(),
+            errors: HashSet::new(),
+            all_errors: false,
+        }
+    }
+}
+
+impl WarningConfig {
+    /// applies a `-W` flag, one of `<name>`, `no-<name>`, `error` or `error=<name>`
+    pub fn apply(&mut self, flag: &str) -> Result<(), String> {
+        let kind = |name| WarningKind::from_name(name).ok_or(format!("unknown warning {name:?}"));
+        if flag == "error" {
+            self.all_errors = true;
+        } else if let Some(name) = flag.strip_prefix("no-") {
+            self.enabled.remove(&kind(name)?);
+        } else if let Some(name) = flag.strip_prefix("error=") {
+            let kind = kind(name)?;
+            self.enabled.insert(kind);
+            self.errors.insert(kind);
+        } else {
+            self.enabled.insert(kind(flag)?);
+        }
+        Ok(())
+    }
+
+    /// how a warning of `kind` is reported, `None` if it is disabled
+    pub fn severity(&self, kind: WarningKind) -> Option<Severity> {
+        if !self.enabled.contains(&kind) {
+            None
+        } else if self.all_errors || self.errors.contains(&kind) {
+            Some(Severity::Error)
+        } else {
+            Some(Severity::Warning)
+        }
+    }
+
+    /// turns `warning` into a diagnostic of the configured severity, `None` if it is disabled
+    pub fn diagnostic(&self, warning: &Warning) -> Option<Diagnostic> {
+        let severity = self.severity(warning.kind())?;
+        Some(Diagnostic {
+            severity,
+            ..Diagnostic::from(warning)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{WarningConfig, WarningKind};
+    use crate::parser::diagnostic::Severity;
+
+    #[test]
+    fn test_apply() {
+        let mut config = WarningConfig::default();
+        assert_eq!(config.severity(WarningKind::UnusedLabel), None);
+        assert_eq!(
+            config.severity(WarningKind::UnreachableCode),
+            Some(Severity::Warning)
+        );
+
+        config.apply("unused-label").unwrap();
+        config.apply("no-location-gap").unwrap();
+        config.apply("error=fall-into-data").unwrap();
+        assert_eq!(
+            config.severity(WarningKind::UnusedLabel),
+            Some(Severity::Warning)
+        );
+        assert_eq!(config.severity(WarningKind::LocationGap), None);
+        assert_eq!(
+            config.severity(WarningKind::FallIntoData),
+            Some(Severity::Error)
+        );
+
+        config.apply("error").unwrap();
+        assert_eq!(
+            config.severity(WarningKind::UnusedLabel),
+            Some(Severity::Error)
+        );
+        assert!(config.apply("no-such-warning").is_err());
+    }
+}